@@ -2,19 +2,42 @@ fn main() {
     windows::build! {
         Microsoft::Web::WebView2::Win32::*,
         Windows::Win32::Foundation::{
-            E_NOINTERFACE, E_POINTER, HINSTANCE, LRESULT, POINT, PWSTR, RECT, SIZE, S_OK,
+            CloseHandle, ERROR_FILE_NOT_FOUND, E_NOINTERFACE, E_OUTOFMEMORY, E_POINTER, HANDLE,
+            HINSTANCE, LRESULT, POINT, POINTL, PWSTR, RECT, SIZE, S_OK,
+        },
+        Windows::Win32::Graphics::Gdi::{
+            ClientToScreen, CreateSolidBrush, DeleteObject, EnumDisplayMonitors, FillRect, GetMonitorInfoW,
+            InvalidateRect, MONITOR_DEFAULTTONEAREST, MonitorFromWindow, UpdateWindow,
+        },
+        Windows::Win32::Security::Cryptography::Core::{
+            CertCreateCertificateContext, CertFreeCertificateContext,
+            CertGetCertificateContextProperty, CertGetNameStringW, CERT_CONTEXT,
+            CERT_NAME_ISSUER_FLAG, CERT_NAME_SIMPLE_DISPLAY_TYPE, CERT_SHA1_HASH_PROP_ID,
+            PKCS_7_ASN_ENCODING, X509_ASN_ENCODING,
         },
-        Windows::Win32::Graphics::Gdi::UpdateWindow,
         Windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx},
         Windows::Win32::System::{
-            Com::{CoTaskMemAlloc, CoTaskMemFree},
+            Com::{
+                CoTaskMemAlloc, CoTaskMemFree, IDataObject, DVASPECT_CONTENT, FORMATETC, STGMEDIUM,
+                TYMED_HGLOBAL,
+            },
             LibraryLoader::GetModuleHandleA,
-            Threading::GetCurrentThreadId,
+            Ole::{
+                IDropTarget, OleInitialize, OleUninitialize, RegisterDragDrop, ReleaseStgMedium,
+                RevokeDragDrop, DROPEFFECT_COPY, DROPEFFECT_NONE,
+            },
+            OleAutomation::IDispatch,
+            SystemServices::CF_HDROP,
+            Threading::{
+                AttachThreadInput, CreateEventW, GetCurrentThreadId, OpenProcess,
+                PROCESS_QUERY_LIMITED_INFORMATION, SetEvent,
+            },
             WinRT::EventRegistrationToken,
         },
         Windows::Win32::UI::{
             HiDpi::{SetProcessDpiAwareness, PROCESS_DPI_AWARENESS},
-            KeyboardAndMouseInput::SetFocus,
+            KeyboardAndMouseInput::{GetKeyState, SetFocus, VK_CONTROL, VK_MENU, VK_SHIFT},
+            Shell::{DragQueryFileW, SHCreateMemStream, HDROP},
             WindowsAndMessaging::*,
         },
     };
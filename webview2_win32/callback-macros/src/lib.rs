@@ -83,6 +83,16 @@ fn impl_completed_callback(ast: &CallbackStruct) -> TokenStream {
         #[implement(Microsoft::Web::WebView2::Win32::#interface)]
         #vis struct #name(Option<#closure>);
 
+        impl CompletedCallback for #name {
+            type Interface = #interface;
+            type Arg1 = #arg_1;
+            type Arg2 = #arg_2;
+
+            fn create(closure: #closure) -> #interface {
+                Self(Some(closure)).into()
+            }
+        }
+
         #[allow(non_snake_case)]
         impl #name {
             pub fn create(
@@ -97,17 +107,13 @@ fn impl_completed_callback(ast: &CallbackStruct) -> TokenStream {
                 >,
                 completed: #closure,
             ) -> crate::Result<()> {
-                let (tx, rx) = mpsc::channel();
-                let completed: #closure =
-                    Box::new(move |arg_1, arg_2| -> ::windows::Result<()> {
-                        let result = completed(arg_1, arg_2).map_err(crate::Error::WindowsError);
-                        tx.send(result).expect("send over mpsc channel");
-                        Ok(())
-                    });
-                let callback = Self::create(completed);
-
-                closure(callback)?;
-                wait_with_pump(rx)?
+                <Self as CompletedCallback>::run_async(
+                    crate::DEFAULT_TIMEOUT,
+                    closure,
+                    move |arg_1, arg_2| {
+                        completed(arg_1, arg_2).map_err(crate::Error::WindowsError)
+                    },
+                )
             }
 
             fn Invoke<'a>(
@@ -1,4 +1,4 @@
-use std::sync::mpsc;
+use std::{sync::mpsc, time::Duration};
 
 use windows::{implement, IUnknown, Interface, HRESULT};
 
@@ -13,7 +13,7 @@ use bindings::{
     },
 };
 
-use super::{pwstr::string_from_pwstr, wait_with_pump};
+use super::{pwstr::string_from_pwstr, wait_with_pump, DEFAULT_TIMEOUT};
 
 pub trait ClosureArg {
     type Output: Sized;
@@ -85,6 +85,42 @@ pub type EventClosure<Arg1, Arg2> = Box<
     dyn FnMut(<Arg1 as ClosureArg>::Output, <Arg2 as ClosureArg>::Output) -> windows::Result<()>,
 >;
 
+/// Common interface for the types generated by [`completed_callback`], which lets
+/// [`CompletedCallback::run_async`] wait for any of them.
+pub trait CompletedCallback {
+    type Interface;
+    type Arg1: ClosureArg;
+    type Arg2: ClosureArg;
+
+    fn create(closure: CompletedClosure<Self::Arg1, Self::Arg2>) -> Self::Interface;
+
+    /// Start an async operation with `start`, passing it a new completed handler, and pump
+    /// messages until the handler is invoked. Returns the result of `completed`, which receives
+    /// the converted arguments of the handler, or [`crate::Error::Timeout`] if the handler is not
+    /// invoked within `timeout`.
+    fn run_async<T, S, F>(timeout: Duration, start: S, completed: F) -> crate::Result<T>
+    where
+        T: 'static,
+        S: FnOnce(Self::Interface) -> crate::Result<()>,
+        F: FnOnce(
+                <Self::Arg1 as ClosureArg>::Output,
+                <Self::Arg2 as ClosureArg>::Output,
+            ) -> crate::Result<T>
+            + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let callback = Self::create(Box::new(move |arg_1, arg_2| -> ::windows::Result<()> {
+            // The receiver is gone if `wait_with_pump` already timed out, which is not an error
+            // worth unwinding across the COM callback for.
+            let _ = tx.send(completed(arg_1, arg_2));
+            Ok(())
+        }));
+
+        start(callback)?;
+        wait_with_pump(rx, timeout)?
+    }
+}
+
 #[completed_callback]
 pub struct CreateCoreWebView2EnvironmentCompletedHandler(
     ICoreWebView2CreateCoreWebView2EnvironmentCompletedHandler,
@@ -244,13 +280,14 @@ impl CapturePreviewCompletedHandler {
         let completed: CapturePreviewCompletedHandlerClosure =
             Box::new(move |arg_1| -> ::windows::Result<()> {
                 let result = completed(arg_1).map_err(crate::Error::WindowsError);
-                tx.send(result).expect("send over mpsc channel");
+                // Ignore a completion which arrives after the wait timed out.
+                let _ = tx.send(result);
                 Ok(())
             });
         let callback = Self::create(completed);
 
         closure(callback)?;
-        wait_with_pump(rx)?
+        wait_with_pump(rx, DEFAULT_TIMEOUT)?
     }
 
     fn Invoke<'a>(&mut self, arg_1: <HRESULT as InvokeArg<'a>>::Input) -> ::windows::Result<()> {
@@ -0,0 +1,89 @@
+use std::{ffi::c_void, ptr};
+
+use bindings::Windows::Win32::{
+    Foundation::PWSTR,
+    Security::Cryptography::Core::{
+        CertCreateCertificateContext, CertFreeCertificateContext,
+        CertGetCertificateContextProperty, CertGetNameStringW, CERT_CONTEXT, CERT_NAME_ISSUER_FLAG,
+        CERT_NAME_SIMPLE_DISPLAY_TYPE, CERT_SHA1_HASH_PROP_ID, PKCS_7_ASN_ENCODING,
+        X509_ASN_ENCODING,
+    },
+};
+
+/// The parts of a server certificate which [`crate::CertErrorInfo`] reports.
+#[derive(Clone, Debug, Default)]
+pub struct Details {
+    pub subject: String,
+    pub issuer: String,
+    pub thumbprint: String,
+}
+
+/// Read the subject and issuer display names and the SHA-1 thumbprint from a DER encoded X.509
+/// certificate, or `None` if CryptoAPI cannot parse it.
+pub fn parse(der: &[u8]) -> Option<Details> {
+    let context = unsafe {
+        CertCreateCertificateContext(
+            X509_ASN_ENCODING | PKCS_7_ASN_ENCODING,
+            der.as_ptr(),
+            der.len() as u32,
+        )
+    };
+    if context.is_null() {
+        return None;
+    }
+
+    let details = Details {
+        subject: name_string(context, 0),
+        issuer: name_string(context, CERT_NAME_ISSUER_FLAG),
+        thumbprint: thumbprint(context),
+    };
+    unsafe { CertFreeCertificateContext(context) };
+    Some(details)
+}
+
+fn name_string(context: *const CERT_CONTEXT, flags: u32) -> String {
+    let len = unsafe {
+        CertGetNameStringW(
+            context,
+            CERT_NAME_SIMPLE_DISPLAY_TYPE,
+            flags,
+            ptr::null_mut(),
+            PWSTR::default(),
+            0,
+        )
+    };
+    let mut buffer = vec![0u16; len as usize];
+    let len = unsafe {
+        CertGetNameStringW(
+            context,
+            CERT_NAME_SIMPLE_DISPLAY_TYPE,
+            flags,
+            ptr::null_mut(),
+            PWSTR(buffer.as_mut_ptr()),
+            buffer.len() as u32,
+        )
+    };
+    // The length includes the terminating null.
+    String::from_utf16_lossy(&buffer[..(len as usize).saturating_sub(1)])
+}
+
+/// Format the SHA-1 hash of the certificate the way the Windows certificate dialog shows it.
+fn thumbprint(context: *const CERT_CONTEXT) -> String {
+    let mut hash = [0u8; 20];
+    let mut len = hash.len() as u32;
+    let found = unsafe {
+        CertGetCertificateContextProperty(
+            context,
+            CERT_SHA1_HASH_PROP_ID,
+            hash.as_mut_ptr() as *mut c_void,
+            &mut len,
+        )
+    };
+    if !found.as_bool() {
+        return String::new();
+    }
+    hash[..len as usize]
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect()
+}
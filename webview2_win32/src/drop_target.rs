@@ -0,0 +1,197 @@
+use std::{ffi::CString, path::PathBuf, ptr};
+
+use windows::implement;
+
+use bindings::Windows::{
+    self,
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, POINTL, PSTR, PWSTR, WPARAM},
+        System::{
+            Com::{IDataObject, DVASPECT_CONTENT, FORMATETC, STGMEDIUM, TYMED_HGLOBAL},
+            LibraryLoader,
+            Ole::{self, DROPEFFECT_COPY, DROPEFFECT_NONE},
+            SystemServices::CF_HDROP,
+        },
+        UI::{
+            Shell::{self, HDROP},
+            WindowsAndMessaging::{self, WNDCLASSA},
+        },
+    },
+};
+
+const OVERLAY_CLASS_NAME: &str = "WebView2FileDropOverlay";
+
+/// Create the window [`FileDropTarget`] is registered on. It is an almost transparent popup
+/// owned by `owner`, hidden until [`show_overlay`] moves it over the WebView, because the
+/// WebView2 child window registers its own drop target which would otherwise take the drop.
+pub fn create_overlay(owner: HWND) -> HWND {
+    let c_class_name = CString::new(OVERLAY_CLASS_NAME).expect("lpszClassName");
+    let window_class = WNDCLASSA {
+        lpfnWndProc: Some(overlay_proc),
+        lpszClassName: PSTR(c_class_name.as_ptr() as *mut _),
+        ..WNDCLASSA::default()
+    };
+
+    unsafe {
+        WindowsAndMessaging::RegisterClassA(&window_class);
+
+        let hwnd = WindowsAndMessaging::CreateWindowExA(
+            WindowsAndMessaging::WS_EX_LAYERED
+                | WindowsAndMessaging::WS_EX_TOOLWINDOW
+                | WindowsAndMessaging::WS_EX_NOACTIVATE,
+            OVERLAY_CLASS_NAME,
+            OVERLAY_CLASS_NAME,
+            WindowsAndMessaging::WS_POPUP,
+            0,
+            0,
+            0,
+            0,
+            owner,
+            None,
+            LibraryLoader::GetModuleHandleA(None),
+            ptr::null_mut(),
+        );
+        // A fully transparent layered window does not get hit tested, so the drop would fall
+        // through to the WebView again.
+        WindowsAndMessaging::SetLayeredWindowAttributes(hwnd, 0, 1, WindowsAndMessaging::LWA_ALPHA);
+        hwnd
+    }
+}
+
+/// Show the overlay at `x`, `y` in screen coordinates, on top and without activating it.
+pub fn show_overlay(overlay: HWND, x: i32, y: i32, width: i32, height: i32) {
+    unsafe {
+        WindowsAndMessaging::SetWindowPos(
+            overlay,
+            WindowsAndMessaging::HWND_TOP,
+            x,
+            y,
+            width,
+            height,
+            WindowsAndMessaging::SWP_NOACTIVATE | WindowsAndMessaging::SWP_SHOWWINDOW,
+        );
+    }
+}
+
+fn hide_overlay(overlay: HWND) {
+    unsafe {
+        WindowsAndMessaging::ShowWindow(overlay, WindowsAndMessaging::SW_HIDE);
+    }
+}
+
+extern "system" fn overlay_proc(hwnd: HWND, msg: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+    unsafe { WindowsAndMessaging::DefWindowProcA(hwnd, msg, w_param, l_param) }
+}
+
+/// Implementation of [`Ole::IDropTarget`] which accepts files dragged from Explorer onto the
+/// overlay and passes all of their paths to the callback.
+#[implement(Windows::Win32::System::Ole::IDropTarget)]
+pub struct FileDropTarget {
+    overlay: HWND,
+    effect: u32,
+    f: Box<dyn FnMut(Vec<PathBuf>)>,
+}
+
+#[allow(non_snake_case)]
+impl FileDropTarget {
+    pub fn create(overlay: HWND, f: Box<dyn FnMut(Vec<PathBuf>)>) -> Ole::IDropTarget {
+        Self {
+            overlay,
+            effect: DROPEFFECT_NONE,
+            f,
+        }
+        .into()
+    }
+
+    fn DragEnter(
+        &mut self,
+        data: &Option<IDataObject>,
+        _key_state: u32,
+        _pt: POINTL,
+        effect: *mut u32,
+    ) -> ::windows::Result<()> {
+        self.effect = match data {
+            Some(data) if unsafe { data.QueryGetData(&hdrop_format()) }.is_ok() => DROPEFFECT_COPY,
+            _ => DROPEFFECT_NONE,
+        };
+        set_effect(effect, self.effect);
+        if self.effect == DROPEFFECT_NONE {
+            hide_overlay(self.overlay);
+        }
+        Ok(())
+    }
+
+    fn DragOver(
+        &mut self,
+        _key_state: u32,
+        _pt: POINTL,
+        effect: *mut u32,
+    ) -> ::windows::Result<()> {
+        set_effect(effect, self.effect);
+        Ok(())
+    }
+
+    fn DragLeave(&mut self) -> ::windows::Result<()> {
+        hide_overlay(self.overlay);
+        Ok(())
+    }
+
+    fn Drop(
+        &mut self,
+        data: &Option<IDataObject>,
+        _key_state: u32,
+        _pt: POINTL,
+        effect: *mut u32,
+    ) -> ::windows::Result<()> {
+        hide_overlay(self.overlay);
+        set_effect(effect, self.effect);
+        if self.effect == DROPEFFECT_NONE {
+            return Ok(());
+        }
+        if let Some(data) = data {
+            let paths = dropped_paths(data)?;
+            if !paths.is_empty() {
+                (self.f)(paths);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hdrop_format() -> FORMATETC {
+    FORMATETC {
+        cfFormat: CF_HDROP.0 as u16,
+        ptd: ptr::null_mut(),
+        dwAspect: DVASPECT_CONTENT.0 as u32,
+        lindex: -1,
+        tymed: TYMED_HGLOBAL.0 as u32,
+    }
+}
+
+fn set_effect(effect: *mut u32, value: u32) {
+    if !effect.is_null() {
+        unsafe { *effect = value };
+    }
+}
+
+/// Read every path from the `CF_HDROP` data of `data`.
+fn dropped_paths(data: &IDataObject) -> ::windows::Result<Vec<PathBuf>> {
+    let mut medium = STGMEDIUM::default();
+    unsafe { data.GetData(&hdrop_format(), &mut medium) }?;
+
+    let hdrop = HDROP(unsafe { medium.Anonymous.hGlobal });
+    let count = unsafe { Shell::DragQueryFileW(hdrop, u32::MAX, PWSTR::default(), 0) };
+    let paths = (0..count)
+        .map(|i| {
+            let len = unsafe { Shell::DragQueryFileW(hdrop, i, PWSTR::default(), 0) };
+            let mut buffer = vec![0u16; len as usize + 1];
+            let len = unsafe {
+                Shell::DragQueryFileW(hdrop, i, PWSTR(buffer.as_mut_ptr()), buffer.len() as u32)
+            };
+            PathBuf::from(String::from_utf16_lossy(&buffer[..len as usize]))
+        })
+        .collect();
+
+    unsafe { Ole::ReleaseStgMedium(&mut medium) };
+    Ok(paths)
+}
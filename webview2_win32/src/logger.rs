@@ -0,0 +1,47 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+};
+
+type Logger = Box<dyn Fn(&str) + Send + Sync>;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOGGER: RwLock<Option<Logger>> = RwLock::new(None);
+
+/// Install `f` to receive trace messages from key points inside the `WebView`, e.g. when the
+/// environment and controller are created, navigations start and complete, or an async
+/// operation times out. This replaces any previous logger.
+pub fn set_logger<F>(f: F)
+where
+    F: Fn(&str) + Send + Sync + 'static,
+{
+    if let Ok(mut logger) = LOGGER.write() {
+        *logger = Some(Box::new(f));
+        ENABLED.store(true, Ordering::Release);
+    }
+}
+
+/// Remove the logger installed with [`set_logger`].
+pub fn clear_logger() {
+    ENABLED.store(false, Ordering::Release);
+    if let Ok(mut logger) = LOGGER.write() {
+        *logger = None;
+    }
+}
+
+/// Send a message to the logger. Without a logger this is just an atomic load, and the message
+/// is never formatted.
+pub fn log(message: fmt::Arguments) {
+    if !ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+
+    if let Ok(logger) = LOGGER.read() {
+        if let Some(logger) = logger.as_ref() {
+            logger(&message.to_string());
+        }
+    }
+}
@@ -1,36 +1,66 @@
+//! The WebView2 bindings in this sample are generated from an older WebView2 SDK than the runtime
+//! most machines have installed, so newer interfaces, e.g. `ICoreWebView2_5` and later or
+//! `ICoreWebView2Profile`, are missing from them. Methods which would use one of those fall back
+//! to the DevTools Protocol or to scripts in the page instead, and their docs note how the
+//! fallback differs from the native API.
+
 #![windows_subsystem = "windows"]
 
 use std::{
     collections::HashMap,
+    convert::TryFrom,
     ffi::CString,
-    mem, ptr,
-    sync::{mpsc, Arc, Mutex},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
 use serde_json::{Number, Value};
 use windows::*;
 
 use bindings::{
     Microsoft::Web::WebView2::Win32::*,
-    Windows::Win32::{Foundation::E_POINTER, System::Com::*},
     Windows::Win32::{
-        Foundation::{HWND, LPARAM, LRESULT, PSTR, PWSTR, RECT, SIZE, WPARAM},
+        Foundation::{
+            CloseHandle, BOOL, HANDLE, HINSTANCE, HWND, LPARAM, LRESULT, POINT, PSTR, PWSTR, RECT,
+            SIZE, WPARAM,
+        },
         Graphics::Gdi,
-        System::{LibraryLoader, Threading, WinRT::EventRegistrationToken},
+        Storage::StructuredStorage::IStream,
+        System::{
+            LibraryLoader, Ole, OleAutomation::IDispatch, Threading, WinRT::EventRegistrationToken,
+        },
         UI::{
-            HiDpi, KeyboardAndMouseInput,
-            WindowsAndMessaging::{self, MSG, WINDOW_LONG_PTR_INDEX, WNDCLASSA},
+            HiDpi, KeyboardAndMouseInput, Shell,
+            WindowsAndMessaging::{self, HICON, MINMAXINFO, MSG, WINDOW_LONG_PTR_INDEX, WNDCLASSA},
         },
     },
+    Windows::Win32::{
+        Foundation::{ERROR_FILE_NOT_FOUND, E_OUTOFMEMORY, E_POINTER},
+        System::Com::*,
+    },
 };
 
 #[macro_use]
 extern crate callback_macros;
 
 mod callback;
+mod certificate;
+mod drop_target;
+mod logger;
+mod options;
 mod pwstr;
 
+use callback::CompletedCallback;
+pub use logger::{clear_logger, set_logger};
+
 fn main() -> Result<()> {
     unsafe {
         CoInitializeEx(std::ptr::null_mut(), COINIT_APARTMENTTHREADED)?;
@@ -73,7 +103,27 @@ pub enum Error {
     WindowsError(windows::Error),
     JsonError(serde_json::Error),
     CallbackError(String),
+    IconError(String),
+    IoError(std::io::Error),
+    InvalidProfileName(String),
+    InvalidBrowserExecutableFolder(String),
+    InvalidWindowStyle,
+    InvalidLanguageTag(String),
+    FrameNotFound(String),
+    MonitorNotFound(usize),
+    EmptyCaptureRegion,
+    InvalidHostName(String),
+    ScriptError(String),
+    DevToolsProtocolError(String),
+    UnsupportedInterface(&'static str),
+    /// The WebView2 runtime is not installed, or the folder passed to
+    /// [`WebViewBuilder::with_browser_executable_folder`] does not contain one. Users can get the
+    /// Evergreen runtime from <https://go.microsoft.com/fwlink/p/?LinkId=2124703>.
+    RuntimeNotInstalled,
     TaskCanceled,
+    AlreadyRunning,
+    Timeout,
+    WrongThread,
     LockError,
     SendError,
 }
@@ -90,6 +140,12 @@ impl From<HRESULT> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(err: serde_json::Error) -> Self {
         Self::JsonError(err)
@@ -120,16 +176,45 @@ impl Drop for Window {
     }
 }
 
+struct Icon {
+    small: HICON,
+    big: HICON,
+}
+
+impl Drop for Icon {
+    fn drop(&mut self) {
+        unsafe {
+            WindowsAndMessaging::DestroyIcon(self.small);
+            WindowsAndMessaging::DestroyIcon(self.big);
+        }
+    }
+}
+
+type CloseRequestedCallback = Box<dyn FnMut() -> bool>;
+
 #[derive(Clone)]
 pub struct FrameWindow {
     window: Arc<HWND>,
     size: Arc<Mutex<SIZE>>,
+    min_size: Arc<Mutex<Option<SIZE>>>,
+    max_size: Arc<Mutex<Option<SIZE>>>,
+    icon: Arc<Mutex<Option<Icon>>>,
+    close_requested: Arc<Mutex<Option<CloseRequestedCallback>>>,
+    background_color: Arc<Mutex<Option<u32>>>,
+    windowed: Arc<Mutex<Option<WindowedState>>>,
 }
 
+/// What [`FrameWindow::set_fullscreen`] restores when leaving fullscreen.
+type WindowedState = (u32, WindowsAndMessaging::WINDOWPLACEMENT);
+
 impl FrameWindow {
-    fn new() -> Self {
+    fn new(
+        class_name: &str,
+        style: WindowsAndMessaging::WINDOW_STYLE,
+        ex_style: WindowsAndMessaging::WINDOW_EX_STYLE,
+        bounds: Option<RECT>,
+    ) -> Self {
         let hwnd = {
-            let class_name = "WebView";
             let c_class_name = CString::new(class_name).expect("lpszClassName");
             let window_class = WNDCLASSA {
                 lpfnWndProc: Some(window_proc),
@@ -140,15 +225,30 @@ impl FrameWindow {
             unsafe {
                 WindowsAndMessaging::RegisterClassA(&window_class);
 
+                let (x, y, width, height) = match bounds {
+                    Some(bounds) => (
+                        bounds.left,
+                        bounds.top,
+                        bounds.right - bounds.left,
+                        bounds.bottom - bounds.top,
+                    ),
+                    None => (
+                        WindowsAndMessaging::CW_USEDEFAULT,
+                        WindowsAndMessaging::CW_USEDEFAULT,
+                        WindowsAndMessaging::CW_USEDEFAULT,
+                        WindowsAndMessaging::CW_USEDEFAULT,
+                    ),
+                };
+
                 WindowsAndMessaging::CreateWindowExA(
-                    Default::default(),
+                    ex_style,
                     class_name,
                     class_name,
-                    WindowsAndMessaging::WS_OVERLAPPEDWINDOW,
-                    WindowsAndMessaging::CW_USEDEFAULT,
-                    WindowsAndMessaging::CW_USEDEFAULT,
-                    WindowsAndMessaging::CW_USEDEFAULT,
-                    WindowsAndMessaging::CW_USEDEFAULT,
+                    style,
+                    x,
+                    y,
+                    width,
+                    height,
                     None,
                     None,
                     LibraryLoader::GetModuleHandleA(None),
@@ -160,351 +260,4901 @@ impl FrameWindow {
         FrameWindow {
             window: Arc::new(hwnd),
             size: Arc::new(Mutex::new(SIZE { cx: 0, cy: 0 })),
+            min_size: Arc::new(Mutex::new(None)),
+            max_size: Arc::new(Mutex::new(None)),
+            icon: Arc::new(Mutex::new(None)),
+            close_requested: Arc::new(Mutex::new(None)),
+            background_color: Arc::new(Mutex::new(None)),
+            windowed: Arc::new(Mutex::new(None)),
         }
     }
-}
 
-struct WebViewController(ICoreWebView2Controller);
+    /// Switch to borderless fullscreen on the monitor the window is on, or back to the style,
+    /// position and maximized state it had before.
+    fn set_fullscreen(&self, fullscreen: bool) -> Result<()> {
+        let window = *self.window;
+        let mut windowed = self.windowed.lock()?;
+        match (fullscreen, windowed.take()) {
+            (true, None) => unsafe {
+                let style = GetWindowLong(window, WindowsAndMessaging::GWL_STYLE) as u32;
+                let mut placement = WindowsAndMessaging::WINDOWPLACEMENT {
+                    length: mem::size_of::<WindowsAndMessaging::WINDOWPLACEMENT>() as u32,
+                    ..Default::default()
+                };
+                let mut info = Gdi::MONITORINFO {
+                    cbSize: mem::size_of::<Gdi::MONITORINFO>() as u32,
+                    ..Default::default()
+                };
+                let monitor = Gdi::MonitorFromWindow(window, Gdi::MONITOR_DEFAULTTONEAREST);
+                if !WindowsAndMessaging::GetWindowPlacement(window, &mut placement).as_bool()
+                    || !Gdi::GetMonitorInfoW(monitor, &mut info).as_bool()
+                {
+                    return Err(windows::Error::from_win32().into());
+                }
+                *windowed = Some((style, placement));
 
-type WebViewSender = mpsc::Sender<Box<dyn FnOnce(WebView) + Send>>;
-type WebViewReceiver = mpsc::Receiver<Box<dyn FnOnce(WebView) + Send>>;
-type BindingCallback = Box<dyn FnMut(Vec<Value>) -> Result<Value>>;
-type BindingsMap = HashMap<String, BindingCallback>;
+                SetWindowLong(
+                    window,
+                    WindowsAndMessaging::GWL_STYLE,
+                    (style & !WindowsAndMessaging::WS_OVERLAPPEDWINDOW.0) as isize,
+                );
+                let rect = info.rcMonitor;
+                WindowsAndMessaging::SetWindowPos(
+                    window,
+                    None,
+                    rect.left,
+                    rect.top,
+                    rect.right - rect.left,
+                    rect.bottom - rect.top,
+                    WindowsAndMessaging::SWP_FRAMECHANGED
+                        | WindowsAndMessaging::SWP_NOZORDER
+                        | WindowsAndMessaging::SWP_NOOWNERZORDER,
+                );
+            },
+            (false, Some((style, placement))) => unsafe {
+                SetWindowLong(window, WindowsAndMessaging::GWL_STYLE, style as isize);
+                WindowsAndMessaging::SetWindowPlacement(window, &placement);
+                WindowsAndMessaging::SetWindowPos(
+                    window,
+                    None,
+                    0,
+                    0,
+                    0,
+                    0,
+                    WindowsAndMessaging::SWP_FRAMECHANGED
+                        | WindowsAndMessaging::SWP_NOMOVE
+                        | WindowsAndMessaging::SWP_NOSIZE
+                        | WindowsAndMessaging::SWP_NOZORDER
+                        | WindowsAndMessaging::SWP_NOOWNERZORDER,
+                );
+            },
+            // Already in the requested state.
+            (_, state) => *windowed = state,
+        }
+        Ok(())
+    }
 
-#[derive(Clone)]
-pub struct WebView {
-    controller: Arc<WebViewController>,
-    webview: Arc<ICoreWebView2>,
-    tx: WebViewSender,
-    rx: Arc<WebViewReceiver>,
-    thread_id: u32,
-    bindings: Arc<Mutex<BindingsMap>>,
-    frame: Option<FrameWindow>,
-    parent: Arc<HWND>,
-    url: Arc<Mutex<String>>,
-}
+    fn set_icon(
+        &self,
+        instance: HINSTANCE,
+        name: PWSTR,
+        flags: WindowsAndMessaging::IMAGE_FLAGS,
+    ) -> Result<()> {
+        let load_icon = |cx, cy| {
+            let icon = unsafe {
+                WindowsAndMessaging::LoadImageW(
+                    instance,
+                    name,
+                    WindowsAndMessaging::IMAGE_ICON,
+                    WindowsAndMessaging::GetSystemMetrics(cx),
+                    WindowsAndMessaging::GetSystemMetrics(cy),
+                    flags,
+                )
+            };
+            match icon.0 {
+                0 => Err(windows::Error::from_win32()),
+                icon => Ok(HICON(icon)),
+            }
+        };
 
-impl Drop for WebViewController {
-    fn drop(&mut self) {
-        unsafe { self.0.Close() }.unwrap();
+        let small = load_icon(
+            WindowsAndMessaging::SM_CXSMICON,
+            WindowsAndMessaging::SM_CYSMICON,
+        )?;
+        let big = match load_icon(
+            WindowsAndMessaging::SM_CXICON,
+            WindowsAndMessaging::SM_CYICON,
+        ) {
+            Ok(big) => big,
+            Err(err) => {
+                unsafe {
+                    WindowsAndMessaging::DestroyIcon(small);
+                }
+                return Err(err.into());
+            }
+        };
+        let icon = Icon { small, big };
+
+        unsafe {
+            WindowsAndMessaging::SendMessageW(
+                *self.window,
+                WindowsAndMessaging::WM_SETICON,
+                WPARAM(WindowsAndMessaging::ICON_SMALL as _),
+                LPARAM(icon.small.0),
+            );
+            WindowsAndMessaging::SendMessageW(
+                *self.window,
+                WindowsAndMessaging::WM_SETICON,
+                WPARAM(WindowsAndMessaging::ICON_BIG as _),
+                LPARAM(icon.big.0),
+            );
+        }
+
+        // Replacing the previous icon drops it, which destroys the old handles now that the
+        // window is no longer using them.
+        *self.icon.lock()? = Some(icon);
+        Ok(())
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct InvokeMessage {
-    id: u64,
-    method: String,
-    params: Vec<Value>,
+/// Options for creating a [`WebView`], for settings which [`WebView::create`] does not take.
+///
+/// ```ignore
+/// let work = WebViewBuilder::new().with_profile_name("work").build()?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WebViewBuilder {
+    parent: Option<HWND>,
+    bounds: Option<RECT>,
+    debug: bool,
+    profile_name: Option<String>,
+    url: Option<String>,
+    timeout: Option<Duration>,
+    browser_executable_folder: Option<String>,
+    class_name: Option<String>,
+    style: Option<WindowsAndMessaging::WINDOW_STYLE>,
+    ex_style: Option<WindowsAndMessaging::WINDOW_EX_STYLE>,
+    no_focus_contents_on_run: bool,
+    no_auto_fullscreen: bool,
+    language: Option<String>,
+    muted: bool,
+    maximized: bool,
+    monitor: Option<usize>,
+    cookies: Vec<Cookie>,
+    ephemeral_profile: bool,
+    temp_profile: Option<Arc<TempProfile>>,
 }
 
-impl WebView {
-    pub fn create(parent: Option<HWND>, debug: bool) -> Result<WebView> {
-        let (parent, frame) = match parent {
-            Some(hwnd) => (hwnd, None),
-            None => {
-                let frame = FrameWindow::new();
-                (*frame.window, Some(frame))
-            }
-        };
+impl WebViewBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        let environment = {
-            let (tx, rx) = mpsc::channel();
-
-            callback::CreateCoreWebView2EnvironmentCompletedHandler::wait_for_async_operation(
-                Box::new(|environmentcreatedhandler| unsafe {
-                    CreateCoreWebView2Environment(environmentcreatedhandler)
-                        .map_err(Error::WindowsError)
-                }),
-                Box::new(move |error_code, environment| {
-                    error_code?;
-                    tx.send(environment.ok_or_else(|| windows::Error::fast_error(E_POINTER)))
-                        .expect("send over mpsc channel");
-                    Ok(())
-                }),
-            )?;
+    /// Embed the `WebView` in `parent` instead of creating a frame window for it.
+    pub fn with_parent(mut self, parent: HWND) -> Self {
+        self.parent = Some(parent);
+        self
+    }
 
-            rx.recv().map_err(|_| Error::SendError)?
-        }?;
+    /// Only cover `bounds` in the client area of the parent, see [`WebView::create_with_bounds`].
+    pub fn with_bounds(mut self, bounds: RECT) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
 
-        let controller = {
-            let (tx, rx) = mpsc::channel();
-
-            callback::CreateCoreWebView2ControllerCompletedHandler::wait_for_async_operation(
-                Box::new(move |handler| unsafe {
-                    environment
-                        .CreateCoreWebView2Controller(parent, handler)
-                        .map_err(Error::WindowsError)
-                }),
-                Box::new(move |error_code, controller| {
-                    error_code?;
-                    tx.send(controller.ok_or_else(|| windows::Error::fast_error(E_POINTER)))
-                        .expect("send over mpsc channel");
-                    Ok(())
-                }),
-            )?;
+    /// Leave the DevTools and the default context menus enabled.
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
 
-            rx.recv().map_err(|_| Error::SendError)?
-        }?;
+    /// Keep cookies, storage and cache for this `WebView` apart from every other profile, e.g.
+    /// to sign in to two accounts at once. `WebView`s with the same name share a profile, and
+    /// without a name the default profile is used.
+    ///
+    /// Names may contain up to 64 ASCII letters, digits, spaces and ``#@$()+-_~.`` characters,
+    /// but must not end with a space or a period, otherwise [`WebViewBuilder::build`] returns
+    /// [`Error::InvalidProfileName`].
+    ///
+    /// Without `ICoreWebView2ControllerOptions::put_ProfileName`, each profile gets its own user
+    /// data folder next to the default one instead, and with it a separate browser process.
+    pub fn with_profile_name(mut self, name: &str) -> Self {
+        self.profile_name = Some(String::from(name));
+        self
+    }
 
-        let size = get_window_size(parent);
-        let mut client_rect = RECT::default();
-        unsafe {
-            WindowsAndMessaging::GetClientRect(parent, &mut client_rect);
-            controller.put_Bounds(RECT {
-                left: 0,
-                top: 0,
-                right: size.cx,
-                bottom: size.cy,
-            })?;
-            controller.put_IsVisible(true)?;
-        }
+    /// Keep cookies, storage and cache in a new user data folder under `%TEMP%`, which is
+    /// deleted again once the last clone of the `WebView` is dropped, e.g. for a guest session.
+    /// This takes precedence over [`WebViewBuilder::with_profile_name`].
+    ///
+    /// The cleanup is best-effort: the browser process only lets go of the files once it exits,
+    /// which takes a moment after the controller is closed, so a background thread keeps trying
+    /// to delete the folder for a while. If the app exits before that, the next ephemeral profile
+    /// the app creates deletes the folder instead. This is not a real InPrivate mode either,
+    /// since the data is written to disk until then.
+    pub fn with_ephemeral_profile(mut self) -> Self {
+        self.ephemeral_profile = true;
+        self
+    }
 
-        let webview = unsafe { controller.get_CoreWebView2()? };
+    /// Register the frame window with `class_name` instead of `WebView`, e.g. so several apps
+    /// using this crate can tell their windows apart. Ignored for embedded `WebView`s.
+    pub fn with_class_name(mut self, class_name: &str) -> Self {
+        self.class_name = Some(String::from(class_name));
+        self
+    }
 
-        if !debug {
-            unsafe {
-                let settings = webview.get_Settings()?;
-                settings.put_AreDefaultContextMenusEnabled(false)?;
-                settings.put_AreDevToolsEnabled(false)?;
-            }
-        }
+    /// Create the frame window with `style` and `ex_style` instead of `WS_OVERLAPPEDWINDOW`,
+    /// e.g. `WS_POPUP` for a borderless window or `WS_EX_TOOLWINDOW` for a tool window. Ignored
+    /// for embedded `WebView`s.
+    ///
+    /// The `WebView` always fills the client area of the frame, so any style works except
+    /// `WS_CHILD`, which needs a parent and makes [`WebViewBuilder::build`] return
+    /// [`Error::InvalidWindowStyle`]; use [`WebViewBuilder::with_parent`] for that instead.
+    pub fn with_window_style(
+        mut self,
+        style: WindowsAndMessaging::WINDOW_STYLE,
+        ex_style: WindowsAndMessaging::WINDOW_EX_STYLE,
+    ) -> Self {
+        self.style = Some(style);
+        self.ex_style = Some(ex_style);
+        self
+    }
 
-        if let Some(frame) = frame.as_ref() {
-            *frame.size.lock()? = size;
-        }
+    /// Open the frame window maximized, on the monitor passed to [`WebViewBuilder::with_monitor`]
+    /// or wherever Windows places it by default. The window is sized before the `WebView` is
+    /// created, so the first page is laid out for the maximized size. Ignored for embedded
+    /// `WebView`s.
+    pub fn with_maximized(mut self, maximized: bool) -> Self {
+        self.maximized = maximized;
+        self
+    }
 
-        let (tx, rx) = mpsc::channel();
-        let rx = Arc::new(rx);
-        let thread_id = unsafe { Threading::GetCurrentThreadId() };
+    /// Open the frame window on the monitor at `index` in the order `EnumDisplayMonitors` lists
+    /// them, covering the middle of its work area, instead of letting Windows place it.
+    /// [`WebViewBuilder::build`] returns [`Error::MonitorNotFound`] if there is no such monitor.
+    /// Ignored for embedded `WebView`s.
+    pub fn with_monitor(mut self, index: usize) -> Self {
+        self.monitor = Some(index);
+        self
+    }
 
-        let webview = WebView {
-            controller: Arc::new(WebViewController(controller)),
-            webview: Arc::new(webview),
-            tx,
-            rx,
-            thread_id,
-            bindings: Arc::new(Mutex::new(HashMap::new())),
-            frame,
-            parent: Arc::new(parent),
-            url: Arc::new(Mutex::new(String::new())),
-        };
+    /// Show the built-in UI of WebView2, e.g. the default context menus and error pages, in
+    /// `language`, e.g. `fr-FR`, instead of the Windows display language. This does not affect
+    /// the page itself, apart from the `Accept-Language` header and `navigator.language`.
+    /// [`WebViewBuilder::build`] returns [`Error::InvalidLanguageTag`] unless `language` looks
+    /// like a BCP 47 language tag.
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.language = Some(String::from(language));
+        self
+    }
 
-        // Inject the invoke handler.
-        webview
-            .init(r#"window.external = { invoke: s => window.chrome.webview.postMessage(s) };"#)?;
+    /// Use the fixed version of the WebView2 runtime in `folder`, e.g. one shipped alongside the
+    /// app, instead of the installed Evergreen runtime. [`WebViewBuilder::build`] returns
+    /// [`Error::InvalidBrowserExecutableFolder`] if `folder` does not contain
+    /// `msedgewebview2.exe`.
+    pub fn with_browser_executable_folder(mut self, folder: &str) -> Self {
+        self.browser_executable_folder = Some(String::from(folder));
+        self
+    }
 
-        let bindings = webview.bindings.clone();
-        let bound = webview.clone();
-        unsafe {
-            let mut _token = EventRegistrationToken::default();
-            webview.webview.add_WebMessageReceived(
-                callback::WebMessageReceivedEventHandler::create(Box::new(
-                    move |_webview, args| {
-                        if let Some(args) = args {
-                            let mut message = PWSTR::default();
-                            if args.get_WebMessageAsJson(&mut message).is_ok() {
-                                let message = pwstr::take_pwstr(message);
-                                if let Ok(value) = serde_json::from_str::<InvokeMessage>(&message) {
-                                    if let Ok(mut bindings) = bindings.try_lock() {
-                                        if let Some(f) = bindings.get_mut(&value.method) {
-                                            match (*f)(value.params) {
-                                                Ok(result) => bound.resolve(value.id, 0, result),
-                                                Err(err) => bound.resolve(
-                                                    value.id,
-                                                    1,
-                                                    Value::String(format!("{:#?}", err)),
-                                                ),
-                                            }
-                                            .unwrap();
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Ok(())
-                    },
-                )),
-                &mut _token,
-            )?;
-        }
+    /// Give up on WebView2 async operations, e.g. creating the controller, running a script or
+    /// waiting for the first navigation in [`WebView::run`], with [`Error::Timeout`] if they
+    /// take longer than `timeout`. Defaults to 30 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-        if webview.frame.is_some() {
-            WebView::set_window_webview(parent, Some(Box::new(webview.clone())));
-        }
+    /// Whether [`WebView::run`] should call [`WebView::focus_contents`] after showing the frame
+    /// window, which it does by default.
+    pub fn with_focus_contents_on_run(mut self, focus: bool) -> Self {
+        self.no_focus_contents_on_run = !focus;
+        self
+    }
 
-        Ok(webview)
+    /// Whether the frame window should switch to fullscreen, as if by
+    /// [`WebView::set_fullscreen`], while an element in the page is fullscreen, e.g. a video
+    /// after the user clicks its fullscreen button, which it does by default. Turn this off to
+    /// handle [`WebView::on_contains_fullscreen_element_changed`] instead, e.g. to only hide the
+    /// app's own UI around the `WebView`, in which case the element only fills the `WebView`.
+    /// Ignored for embedded `WebView`s.
+    pub fn with_auto_fullscreen(mut self, auto_fullscreen: bool) -> Self {
+        self.no_auto_fullscreen = !auto_fullscreen;
+        self
     }
 
-    pub fn run(self) -> Result<()> {
-        let webview = self.webview.as_ref();
-        let url = self.url.try_lock()?.clone();
-        let (tx, rx) = mpsc::channel();
+    /// Start out muted, as if by [`WebView::set_muted`], so no page ever plays audio until the
+    /// app unmutes it.
+    pub fn with_muted(mut self, muted: bool) -> Self {
+        self.muted = muted;
+        self
+    }
 
-        if !url.is_empty() {
-            let handler = callback::NavigationCompletedEventHandler::create(Box::new(
-                move |_sender, _args| {
-                    tx.send(()).expect("send over mpsc channel");
-                    Ok(())
-                },
-            ));
-            let mut token = EventRegistrationToken::default();
-            unsafe {
-                webview.add_NavigationCompleted(handler, &mut token)?;
-                webview.Navigate(url)?;
-                let result = wait_with_pump(rx);
-                webview.remove_NavigationCompleted(token)?;
-                result?;
-            }
-        }
+    /// Set `cookie`, as if by [`WebView::set_cookie`], before the first navigation, e.g. to hand
+    /// a session over to the page. Call this again for each cookie.
+    ///
+    /// Setting a cookie is asynchronous, so [`WebViewBuilder::build`] waits for every cookie to
+    /// be stored before it returns, and the first request already sends them. Cookies belong to
+    /// the profile rather than the `WebView`, so they are shared with every `WebView` using the
+    /// same profile and, unless they are session cookies, kept for the next launch.
+    pub fn with_cookie(mut self, cookie: Cookie) -> Self {
+        self.cookies.push(cookie);
+        self
+    }
 
-        if let Some(frame) = self.frame.as_ref() {
-            let hwnd = *frame.window;
-            unsafe {
-                WindowsAndMessaging::ShowWindow(hwnd, WindowsAndMessaging::SW_SHOW);
-                Gdi::UpdateWindow(hwnd);
-                KeyboardAndMouseInput::SetFocus(hwnd);
-            }
+    /// Set the URL to load, as if by [`WebView::navigate`].
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = Some(String::from(url));
+        self
+    }
+
+    /// Create the `WebView`. The URL passed to [`WebViewBuilder::with_url`] is loaded when
+    /// [`WebView::run`] is called.
+    pub fn build(self) -> Result<WebView> {
+        let webview = WebView::create_internal(&self)?;
+        if let Some(url) = self.url.as_ref() {
+            webview.navigate(url)?;
         }
+        Ok(webview)
+    }
 
-        let mut msg = MSG::default();
-        let h_wnd = HWND::default();
+    /// Create the `WebView` and load the URL passed to [`WebViewBuilder::with_url`] right away,
+    /// processing messages until the navigation completes, so the `WebView` is ready to use
+    /// without calling [`WebView::run`], e.g. when embedding or in tests. The frame window is
+    /// still hidden until [`WebView::run`] or [`WebView::show`].
+    pub fn build_navigated(self) -> Result<WebView> {
+        let webview = WebView::create_internal(&self)?;
+        if let Some(url) = self.url.as_ref() {
+            webview.navigate_and_wait(url)?;
+        }
+        Ok(webview)
+    }
+}
 
-        loop {
-            while let Ok(f) = self.rx.try_recv() {
-                (f)(self.clone());
-            }
+/// The controller, closed when this is dropped, and the temporary user data folder it uses with
+/// [`WebViewBuilder::with_ephemeral_profile`], which is only released after closing it.
+struct WebViewController(ICoreWebView2Controller, Option<Arc<TempProfile>>);
 
-            unsafe {
-                let result = WindowsAndMessaging::GetMessageA(&mut msg, h_wnd, 0, 0).0;
+/// Temporary folder holding the page for [`WebView::load_html`] when it is too big for
+/// `NavigateToString`, which is deleted along with the folder when this is dropped.
+struct TempHtml(PathBuf);
 
-                match result {
-                    -1 => break Err(windows::Error::from_win32().into()),
-                    0 => break Ok(()),
-                    _ => match msg.message {
-                        WindowsAndMessaging::WM_APP => (),
-                        _ => {
-                            WindowsAndMessaging::TranslateMessage(&msg);
-                            WindowsAndMessaging::DispatchMessageA(&msg);
-                        }
-                    },
-                }
-            }
-        }
+impl TempHtml {
+    fn new(html: &str) -> Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let folder = std::env::temp_dir().join(format!(
+            "webview2-html-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&folder)?;
+        let temp = Self(folder);
+        std::fs::write(temp.0.join("index.html"), html)?;
+        Ok(temp)
     }
+}
 
-    pub fn terminate(self) -> Result<()> {
-        self.dispatch(|_webview| unsafe {
-            WindowsAndMessaging::PostQuitMessage(0);
-        })?;
+impl Drop for TempHtml {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
 
-        if self.frame.is_some() {
-            WebView::set_window_webview(self.get_window(), None);
-        }
+/// Temporary user data folder for [`WebViewBuilder::with_ephemeral_profile`], which is deleted
+/// once the last `WebView` sharing it has closed its controller and dropped its options.
+#[derive(Debug)]
+struct TempProfile(PathBuf);
 
-        Ok(())
+impl TempProfile {
+    fn new() -> Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Self::remove_stale();
+        let folder = std::env::temp_dir().join(format!(
+            "{}{}-{}",
+            TEMP_PROFILE_PREFIX,
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&folder)?;
+        Ok(Self(folder))
     }
 
-    pub fn set_title(&self, title: &str) -> Result<&Self> {
-        if let Some(frame) = self.frame.as_ref() {
-            unsafe {
-                WindowsAndMessaging::SetWindowTextA(*frame.window, title);
+    /// Delete the folders which earlier runs of the app left behind because they exited before
+    /// the browser process let go of them, skipping those of processes which are still running.
+    fn remove_stale() {
+        let entries = match std::fs::read_dir(std::env::temp_dir()) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let process_id = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix(TEMP_PROFILE_PREFIX))
+                .and_then(|name| name.split('-').next())
+                .and_then(|process_id| process_id.parse::<u32>().ok());
+            if let Some(process_id) = process_id {
+                if process_id != std::process::id() && !is_process_running(process_id) {
+                    let _ = std::fs::remove_dir_all(entry.path());
+                }
             }
         }
-        Ok(self)
     }
+}
 
-    pub fn set_size(&self, width: i32, height: i32) -> Result<&Self> {
-        if let Some(frame) = self.frame.as_ref() {
-            *frame.size.lock().expect("lock size") = SIZE {
-                cx: width,
-                cy: height,
-            };
-            unsafe {
-                self.controller.0.put_Bounds(RECT {
-                    left: 0,
-                    top: 0,
-                    right: width,
-                    bottom: height,
-                })?;
-
-                WindowsAndMessaging::SetWindowPos(
-                    *frame.window,
-                    None,
-                    0,
-                    0,
-                    width,
-                    height,
-                    WindowsAndMessaging::SWP_NOACTIVATE
-                        | WindowsAndMessaging::SWP_NOZORDER
-                        | WindowsAndMessaging::SWP_NOMOVE,
-                );
+impl Drop for TempProfile {
+    fn drop(&mut self) {
+        // The browser process keeps files in the folder open until it exits, which happens a
+        // little while after the last controller using it is closed, so keep trying on another
+        // thread instead of blocking the UI thread. If the app exits first, the next
+        // `TempProfile::new` cleans up.
+        let folder = mem::take(&mut self.0);
+        std::thread::spawn(move || {
+            let deadline = Instant::now() + TEMP_PROFILE_CLEANUP_TIMEOUT;
+            while std::fs::remove_dir_all(&folder).is_err()
+                && folder.exists()
+                && Instant::now() < deadline
+            {
+                std::thread::sleep(Duration::from_millis(100));
             }
-        }
-        Ok(self)
+        });
     }
+}
 
-    pub fn get_window(&self) -> HWND {
-        *self.parent
-    }
+/// Shared by every clone of a `WebView` which owns its frame window, so that dropping the last
+/// one quits the message loop the same way [`WebView::terminate`] does, unless it already has.
+/// Embedded `WebView`s leave the message loop to the host.
+struct QuitOnDrop {
+    window: HWND,
+    terminated: Arc<AtomicBool>,
+}
 
-    pub fn navigate(&self, url: &str) -> Result<&Self> {
+impl Drop for QuitOnDrop {
+    fn drop(&mut self) {
+        if !self.terminated.swap(true, Ordering::SeqCst) {
+            WebView::set_window_webview(self.window, None);
+            unsafe { WindowsAndMessaging::PostQuitMessage(0) };
+        }
+    }
+}
+
+/// Auto-reset event which [`WebView::dispatch`] and [`WebViewHandle::dispatch`] signal after
+/// queuing a closure, so [`WebView::run`] wakes up for it even if no window message arrives.
+struct DispatchEvent(HANDLE);
+
+impl DispatchEvent {
+    fn new() -> Result<Self> {
+        let event = unsafe { Threading::CreateEventW(ptr::null(), false, false, PWSTR::default()) };
+        if event.0 == 0 {
+            return Err(windows::Error::from_win32().into());
+        }
+        Ok(Self(event))
+    }
+
+    fn signal(&self) {
+        unsafe { Threading::SetEvent(self.0) };
+    }
+}
+
+impl Drop for DispatchEvent {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+type WebViewSender = mpsc::Sender<Box<dyn FnOnce(WebView) + Send>>;
+type WebViewReceiver = mpsc::Receiver<Box<dyn FnOnce(WebView) + Send>>;
+type BindingCallback = Box<dyn FnMut(&WebView, Vec<Value>) -> Result<Value>>;
+type BindingsMap = HashMap<String, BindingCallback>;
+type EnvironmentSettings = (
+    Option<String>,
+    Option<String>,
+    Option<ICoreWebView2EnvironmentOptions>,
+);
+
+#[derive(Clone)]
+pub struct WebView {
+    inner: Arc<WebViewInner>,
+}
+
+/// The state every clone of a [`WebView`] shares, and which a [`WeakWebView`] refers to.
+#[derive(Clone)]
+pub struct WebViewInner {
+    environment: Arc<ICoreWebView2Environment>,
+    controller: Arc<WebViewController>,
+    webview: Arc<ICoreWebView2>,
+    settings: Arc<ICoreWebView2Settings>,
+    tx: WebViewSender,
+    rx: Arc<WebViewReceiver>,
+    dispatch_event: Arc<DispatchEvent>,
+    thread_id: u32,
+    bindings: Arc<Mutex<BindingsMap>>,
+    web_message_allowed_origins: Arc<Mutex<Vec<String>>>,
+    frame: Option<FrameWindow>,
+    parent: Arc<HWND>,
+    url: Arc<Mutex<String>>,
+    title: Arc<Mutex<String>>,
+    running: Arc<AtomicBool>,
+    timeout: Duration,
+    web_message_received: Arc<Mutex<Option<Subscription>>>,
+    accelerator_keys_blocker: Arc<Mutex<Option<Subscription>>>,
+    loaded_html: Arc<Mutex<Option<TempHtml>>>,
+    mute_script: Arc<Mutex<Option<String>>>,
+    no_swipe_script: Arc<Mutex<Option<String>>>,
+    init_scripts: Arc<Mutex<Vec<(String, String)>>>,
+    fetch_users: Arc<Mutex<usize>>,
+    options: Arc<WebViewBuilder>,
+    focus_contents_on_run: bool,
+    terminated: Arc<AtomicBool>,
+    quit_on_drop: Option<Arc<QuitOnDrop>>,
+}
+
+impl std::ops::Deref for WebView {
+    type Target = WebViewInner;
+
+    fn deref(&self) -> &WebViewInner {
+        &self.inner
+    }
+}
+
+/// A handle to a [`WebView`] which does not keep it alive, from [`WebView::downgrade`].
+///
+/// Event handlers and bindings are owned by the `WebView`, so if they capture a clone of it, the
+/// `WebView` keeps itself alive and the controller is never closed, even after the app has
+/// dropped every other clone. Capture a `WeakWebView` instead, and [`WeakWebView::upgrade`] it
+/// each time the callback runs.
+#[derive(Clone)]
+pub struct WeakWebView(Weak<WebViewInner>);
+
+impl WeakWebView {
+    /// Get the `WebView` back, or `None` once every clone of it has been dropped.
+    pub fn upgrade(&self) -> Option<WebView> {
+        self.0.upgrade().map(|inner| WebView { inner })
+    }
+}
+
+/// A `Send + Sync` handle to a [`WebView`], for queuing work on its UI thread from worker
+/// threads. Get one with [`WebView::handle`].
+///
+/// The COM objects behind a `WebView` may only be used on the thread which created them, so
+/// [`WebViewHandle::dispatch`] is the only operation available. The closure runs on the UI
+/// thread with the `WebView`, where it can `eval`, `navigate`, `post_web_message_json` etc.
+#[derive(Clone)]
+pub struct WebViewHandle {
+    tx: Arc<Mutex<WebViewSender>>,
+    dispatch_event: Arc<DispatchEvent>,
+    thread_id: u32,
+}
+
+impl WebViewHandle {
+    /// Queue `f` to run on the UI thread the next time [`WebView::run`] or
+    /// [`WebView::pump_messages`] processes messages.
+    pub fn dispatch<F>(&self, f: F) -> Result<&Self>
+    where
+        F: FnOnce(WebView) + Send + 'static,
+    {
+        self.tx
+            .lock()?
+            .send(Box::new(f))
+            .map_err(|_| Error::SendError)?;
+        self.wake();
+        Ok(self)
+    }
+
+    /// Wake up the UI thread, like [`MessagePump::wake`], without queuing anything.
+    pub fn wake(&self) {
+        wake_thread(&self.dispatch_event, self.thread_id);
+    }
+}
+
+/// Whether the host should keep calling [`MessagePump::step`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PumpStatus {
+    /// Keep going.
+    Continue,
+    /// `WM_QUIT` was received, so the host should shut down its loop.
+    Quit,
+}
+
+/// How GUI frameworks with their own event loop, e.g. `winit`, drive a [`WebView`] instead of
+/// calling [`WebView::run`].
+///
+/// The host calls [`MessagePump::step`] on the UI thread whenever it wakes up, e.g. once per
+/// iteration of its loop. Other threads queue work with [`WebViewHandle::dispatch`], or call
+/// [`WebViewHandle::wake`], which both post `WM_APP` to the UI thread so a loop waiting in
+/// `GetMessage` or `MsgWaitForMultipleObjects` wakes up and steps again.
+pub trait MessagePump {
+    /// Process queued closures and pending window messages without blocking.
+    fn step(&self) -> Result<PumpStatus>;
+
+    /// Make the UI thread's message loop wake up and call [`MessagePump::step`].
+    fn wake(&self);
+}
+
+impl MessagePump for WebView {
+    fn step(&self) -> Result<PumpStatus> {
+        Ok(if self.pump_messages()? {
+            PumpStatus::Continue
+        } else {
+            PumpStatus::Quit
+        })
+    }
+
+    fn wake(&self) {
+        wake_thread(&self.dispatch_event, self.thread_id);
+    }
+}
+
+/// Signal `dispatch_event` for [`WebView::run`], and post `WM_APP` to `thread_id` for loops which
+/// only wait for messages, e.g. [`WebView::pump_messages`] in a host's own loop.
+fn wake_thread(dispatch_event: &DispatchEvent, thread_id: u32) {
+    dispatch_event.signal();
+
+    unsafe {
+        WindowsAndMessaging::PostThreadMessageA(
+            thread_id,
+            WindowsAndMessaging::WM_APP,
+            WPARAM(0),
+            LPARAM(0),
+        );
+    }
+}
+
+impl Drop for WebViewController {
+    fn drop(&mut self) {
+        unsafe { self.0.Close() }.unwrap();
+    }
+}
+
+/// A key press passed to the callback for [`WebView::on_accelerator_key_pressed`].
+#[derive(Clone, Copy, Debug)]
+pub struct AcceleratorKey {
+    /// The Win32 virtual key code, e.g. `VK_F5`.
+    pub virtual_key: u32,
+    /// `true` for key down events, `false` for key up events.
+    pub key_down: bool,
+    pub control: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+/// A certificate error passed to the callback for [`WebView::on_server_certificate_error`].
+#[derive(Clone, Debug)]
+pub struct CertErrorInfo {
+    /// The network error name, e.g. `net::ERR_CERT_AUTHORITY_INVALID`.
+    pub error_type: String,
+    pub request_uri: String,
+    /// The display name of the certificate's subject, e.g. its common name.
+    pub subject: String,
+    /// The display name of the certificate's issuer.
+    pub issuer: String,
+    /// The SHA-1 hash of the certificate in uppercase hex, as the Windows certificate dialog
+    /// shows it.
+    pub thumbprint: String,
+}
+
+/// What to do about a certificate error in [`WebView::on_server_certificate_error`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CertAction {
+    /// Continue loading the resource despite the error.
+    AlwaysAllow,
+    /// Cancel the request.
+    Cancel,
+    /// Show the default error page, which also cancels the request.
+    Default,
+}
+
+impl CertAction {
+    /// The `action` for the DevTools Protocol `Security.handleCertificateError` method, and
+    /// whether to repeat the navigation without the override, so the runtime shows its own error
+    /// page. The protocol has no action for that, and other requests just fail like a cancel.
+    fn devtools_response(self, is_document: bool) -> (&'static str, bool) {
+        match self {
+            Self::AlwaysAllow => ("continue", false),
+            Self::Cancel => ("cancel", false),
+            Self::Default => ("cancel", is_document),
+        }
+    }
+}
+
+/// The severity of a message passed to the callback for [`WebView::on_console_message`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleLevel {
+    /// `console.debug`.
+    Debug,
+    /// `console.log`, `console.info` and the other console methods without a level of their own.
+    Info,
+    /// `console.warn`.
+    Warning,
+    /// `console.error` and failed `console.assert` calls.
+    Error,
+}
+
+impl ConsoleLevel {
+    fn from_console_api_type(kind: &str) -> Self {
+        match kind {
+            "debug" => ConsoleLevel::Debug,
+            "warning" => ConsoleLevel::Warning,
+            "error" | "assert" => ConsoleLevel::Error,
+            _ => ConsoleLevel::Info,
+        }
+    }
+}
+
+const CONTEXT_MENU_BINDING: &str = "__webviewContextMenuRequested";
+
+/// The `contextmenu` listener added by [`WebView::on_context_menu_requested`], so dropping its
+/// [`Subscription`] can remove it from the current document.
+const CONTEXT_MENU_LISTENER: &str = "__webviewContextMenuListener";
+
+const FAVICON_CHANGED_BINDING: &str = "__webviewFaviconChanged";
+
+const STATUS_BAR_TEXT_BINDING: &str = "__webviewStatusBarTextChanged";
+
+const FILES_DRAGGED_BINDING: &str = "__webviewFilesDragged";
+
+/// Evaluates to the URI of the favicon of the current document, from the last
+/// `<link rel="icon">` element or the default `/favicon.ico` for the origin.
+const FAVICON_URI_SCRIPT: &str = r#"(function() {
+    var icons = document.querySelectorAll('link[rel~="icon"][href]');
+    return icons.length > 0
+        ? icons[icons.length - 1].href
+        : new URL('/favicon.ico', window.location.href).href;
+})()"#;
+
+const AUDIO_STATE_BINDING: &str = "__webviewAudioStateChanged";
+
+/// Defined in the page by [`WebView::on_audio_state_changed`], so [`WebView::set_muted`] can make
+/// it report the state again.
+const REPORT_AUDIO_STATE: &str = "__webviewReportAudioState";
+
+/// Passed to the callback for [`WebView::on_audio_state_changed`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioState {
+    /// Whether [`WebView::set_muted`] is muting the audio.
+    pub muted: bool,
+    /// Whether the page is playing audio, even if it is muted.
+    pub playing: bool,
+}
+
+/// What was under the pointer when the user opened the context menu, passed to the callback for
+/// [`WebView::on_context_menu_requested`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextMenuContext {
+    /// The position of the pointer in CSS pixels, relative to the top left of the `WebView`.
+    /// Multiply by the rasterization scale to get client coordinates for `TrackPopupMenu`.
+    pub x: f64,
+    pub y: f64,
+    pub link_uri: Option<String>,
+    pub image_uri: Option<String>,
+    pub selection_text: Option<String>,
+    pub is_editable: bool,
+}
+
+/// An entry in the back/forward list, returned by [`WebView::history_entries`].
+#[derive(Clone, Debug, Deserialize)]
+pub struct HistoryEntry {
+    /// Pass this to [`WebView::navigate_to_history_entry`] to go back or forward to the entry.
+    pub id: i64,
+    pub url: String,
+    pub title: String,
+    /// Whether this is the entry for the current document.
+    #[serde(skip)]
+    pub is_current: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NavigationHistory {
+    current_index: usize,
+    entries: Vec<HistoryEntry>,
+}
+
+/// How [`WebView::find`] matches the query.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FindOptions {
+    pub case_sensitive: bool,
+    /// Only match the query where it is not part of a longer word.
+    pub whole_word: bool,
+}
+
+/// Returned by [`WebView::find`] and the methods stepping through its matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindResult {
+    pub match_count: usize,
+    /// The index of the selected match, or `None` if there are no matches.
+    pub active_match_index: Option<usize>,
+}
+
+/// Why focus is moving into or out of the `WebView`, see [`WebView::move_focus`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveFocusReason {
+    /// Focus the `WebView` without changing which element in the page has focus.
+    Programmatic,
+    /// Tab forward.
+    Next,
+    /// Tab backward.
+    Previous,
+}
+
+impl From<MoveFocusReason> for COREWEBVIEW2_MOVE_FOCUS_REASON {
+    fn from(reason: MoveFocusReason) -> Self {
+        match reason {
+            MoveFocusReason::Programmatic => COREWEBVIEW2_MOVE_FOCUS_REASON_PROGRAMMATIC,
+            MoveFocusReason::Next => COREWEBVIEW2_MOVE_FOCUS_REASON_NEXT,
+            MoveFocusReason::Previous => COREWEBVIEW2_MOVE_FOCUS_REASON_PREVIOUS,
+        }
+    }
+}
+
+impl From<COREWEBVIEW2_MOVE_FOCUS_REASON> for MoveFocusReason {
+    fn from(reason: COREWEBVIEW2_MOVE_FOCUS_REASON) -> Self {
+        match reason {
+            COREWEBVIEW2_MOVE_FOCUS_REASON_NEXT => Self::Next,
+            COREWEBVIEW2_MOVE_FOCUS_REASON_PREVIOUS => Self::Previous,
+            _ => Self::Programmatic,
+        }
+    }
+}
+
+/// A download passed to the callback for [`WebView::on_download_starting`].
+#[derive(Clone, Debug)]
+pub struct DownloadInfo {
+    pub uri: String,
+    pub mime_type: String,
+    /// The full path where the browser suggests saving the file, including the file name.
+    pub result_file_path: String,
+    /// The size of the download from the `Content-Length` header, if the server sent one.
+    pub total_bytes: Option<u64>,
+}
+
+/// What to do with a download in [`WebView::on_download_starting`].
+#[derive(Clone, Debug)]
+pub enum DownloadDecision {
+    /// Save the file to the suggested path.
+    Allow,
+    Cancel,
+    /// Save the file to a different full path instead of the suggested one.
+    SaveAs(String),
+}
+
+/// The kind of permission passed to the callback for [`WebView::on_permission_requested`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PermissionKind {
+    Unknown,
+    Microphone,
+    Camera,
+    Geolocation,
+    Notifications,
+    OtherSensors,
+    ClipboardRead,
+}
+
+impl From<COREWEBVIEW2_PERMISSION_KIND> for PermissionKind {
+    fn from(kind: COREWEBVIEW2_PERMISSION_KIND) -> Self {
+        match kind {
+            COREWEBVIEW2_PERMISSION_KIND_MICROPHONE => Self::Microphone,
+            COREWEBVIEW2_PERMISSION_KIND_CAMERA => Self::Camera,
+            COREWEBVIEW2_PERMISSION_KIND_GEOLOCATION => Self::Geolocation,
+            COREWEBVIEW2_PERMISSION_KIND_NOTIFICATIONS => Self::Notifications,
+            COREWEBVIEW2_PERMISSION_KIND_OTHER_SENSORS => Self::OtherSensors,
+            COREWEBVIEW2_PERMISSION_KIND_CLIPBOARD_READ => Self::ClipboardRead,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// The answer to a permission request in [`WebView::on_permission_requested`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PermissionState {
+    /// Fall back to the default WebView2 behavior, which usually prompts the user.
+    Default,
+    Allow,
+    Deny,
+}
+
+impl From<PermissionState> for COREWEBVIEW2_PERMISSION_STATE {
+    fn from(state: PermissionState) -> Self {
+        match state {
+            PermissionState::Default => COREWEBVIEW2_PERMISSION_STATE_DEFAULT,
+            PermissionState::Allow => COREWEBVIEW2_PERMISSION_STATE_ALLOW,
+            PermissionState::Deny => COREWEBVIEW2_PERMISSION_STATE_DENY,
+        }
+    }
+}
+
+/// The units for [`WebView::set_bounds`], see [`WebView::set_bounds_mode`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoundsMode {
+    /// Physical pixels.
+    RawPixels,
+    /// DIPs, which are multiplied by the rasterization scale.
+    RasterizationScale,
+}
+
+impl From<BoundsMode> for COREWEBVIEW2_BOUNDS_MODE {
+    fn from(mode: BoundsMode) -> Self {
+        match mode {
+            BoundsMode::RawPixels => COREWEBVIEW2_BOUNDS_MODE_USE_RAW_PIXELS,
+            BoundsMode::RasterizationScale => COREWEBVIEW2_BOUNDS_MODE_USE_RASTERIZATION_SCALE,
+        }
+    }
+}
+
+/// The color scheme for [`WebView::set_preferred_color_scheme`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreferredColorScheme {
+    /// Follow the Windows app mode setting.
+    Auto,
+    Light,
+    Dark,
+}
+
+impl PreferredColorScheme {
+    fn media_value(self) -> &'static str {
+        match self {
+            PreferredColorScheme::Auto => "",
+            PreferredColorScheme::Light => "light",
+            PreferredColorScheme::Dark => "dark",
+        }
+    }
+}
+
+/// Which dialog [`WebView::show_print_ui`] opens, matching `COREWEBVIEW2_PRINT_DIALOG_KIND`.
+/// The system print dialog needs `ICoreWebView2_16`, so it is not available with these bindings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrintDialogKind {
+    /// The browser's print preview, the same one Ctrl+P opens.
+    Browser,
+}
+
+/// The image format for [`WebView::capture_region`] and [`WebView::get_favicon`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    fn devtools_value(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+        }
+    }
+}
+
+/// A set of the kinds of data to remove with [`WebView::clear_browsing_data`], which can be
+/// combined with `|`, e.g. `BrowsingDataKinds::COOKIES | BrowsingDataKinds::DISK_CACHE`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BrowsingDataKinds(u32);
+
+impl BrowsingDataKinds {
+    pub const COOKIES: Self = Self(0x01);
+    pub const DISK_CACHE: Self = Self(0x02);
+    pub const LOCAL_STORAGE: Self = Self(0x04);
+    pub const INDEXED_DB: Self = Self(0x08);
+    pub const WEB_SQL: Self = Self(0x10);
+    pub const FILE_SYSTEMS: Self = Self(0x20);
+    pub const CACHE_STORAGE: Self = Self(0x40);
+    pub const SERVICE_WORKERS: Self = Self(0x80);
+    pub const DOM_STORAGE: Self = Self(0xfc);
+    pub const ALL: Self = Self(0xff);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The `storageTypes` names used by the DevTools Protocol `Storage` domain.
+    fn storage_types(self) -> Vec<&'static str> {
+        [
+            (Self::LOCAL_STORAGE, "local_storage"),
+            (Self::INDEXED_DB, "indexeddb"),
+            (Self::WEB_SQL, "websql"),
+            (Self::FILE_SYSTEMS, "file_systems"),
+            (Self::CACHE_STORAGE, "cache_storage"),
+            (Self::SERVICE_WORKERS, "service_workers"),
+        ]
+        .iter()
+        .filter(|(kind, _)| self.contains(*kind))
+        .map(|(_, name)| *name)
+        .collect()
+    }
+}
+
+impl std::ops::BitOr for BrowsingDataKinds {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A key for [`WebView::send_key`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Enter,
+    Tab,
+    Escape,
+    Backspace,
+    Delete,
+    Space,
+    ArrowLeft,
+    ArrowUp,
+    ArrowRight,
+    ArrowDown,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// The key which types this character without modifiers, e.g. `Key::Character('a')` with
+    /// [`Modifiers::CONTROL`] for Ctrl+A.
+    Character(char),
+}
+
+impl Key {
+    /// The DOM `key` and `code` values, the Windows virtual key code and the text the key types,
+    /// as used by the DevTools Protocol `Input.dispatchKeyEvent` method.
+    fn devtools_values(self) -> (String, String, u32, Option<String>) {
+        let named = |key: &str, vk: u32, text: Option<&str>| {
+            (
+                String::from(key),
+                String::from(key),
+                vk,
+                text.map(String::from),
+            )
+        };
+        match self {
+            Key::Enter => named("Enter", 0x0d, Some("\r")),
+            Key::Tab => named("Tab", 0x09, None),
+            Key::Escape => named("Escape", 0x1b, None),
+            Key::Backspace => named("Backspace", 0x08, None),
+            Key::Delete => named("Delete", 0x2e, None),
+            Key::Space => (
+                String::from(" "),
+                String::from("Space"),
+                0x20,
+                Some(String::from(" ")),
+            ),
+            Key::ArrowLeft => named("ArrowLeft", 0x25, None),
+            Key::ArrowUp => named("ArrowUp", 0x26, None),
+            Key::ArrowRight => named("ArrowRight", 0x27, None),
+            Key::ArrowDown => named("ArrowDown", 0x28, None),
+            Key::Home => named("Home", 0x24, None),
+            Key::End => named("End", 0x23, None),
+            Key::PageUp => named("PageUp", 0x21, None),
+            Key::PageDown => named("PageDown", 0x22, None),
+            Key::Character(c) => {
+                let upper = c.to_ascii_uppercase();
+                let (code, vk) = if upper.is_ascii_uppercase() {
+                    (format!("Key{}", upper), upper as u32)
+                } else if c.is_ascii_digit() {
+                    (format!("Digit{}", c), c as u32)
+                } else {
+                    (String::new(), 0)
+                };
+                (c.to_string(), code, vk, Some(c.to_string()))
+            }
+        }
+    }
+}
+
+/// A set of modifier keys held down for [`WebView::send_key`], which can be combined with `|`,
+/// e.g. `Modifiers::CONTROL | Modifiers::SHIFT`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers(u32);
+
+impl Modifiers {
+    // The values of the `modifiers` bit field in the DevTools Protocol `Input` domain.
+    pub const NONE: Self = Self(0);
+    pub const ALT: Self = Self(0x01);
+    pub const CONTROL: Self = Self(0x02);
+    pub const META: Self = Self(0x04);
+    pub const SHIFT: Self = Self(0x08);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A mouse button for [`WebView::click`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl MouseButton {
+    /// The `button` value used by the DevTools Protocol `Input.dispatchMouseEvent` method.
+    fn devtools_value(self) -> &'static str {
+        match self {
+            MouseButton::Left => "left",
+            MouseButton::Middle => "middle",
+            MouseButton::Right => "right",
+        }
+    }
+}
+
+/// The ways of zooming, navigating and using keyboard shortcuts to allow, for
+/// [`WebView::set_input_policy`]. The default allows everything, like a new `WebView`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputPolicy {
+    /// Zooming with Ctrl+wheel, Ctrl+plus and Ctrl+minus.
+    pub zoom_control_enabled: bool,
+    /// See [`WebView::set_swipe_navigation_enabled`].
+    pub swipe_navigation_enabled: bool,
+    /// See [`WebView::set_pinch_zoom_enabled`].
+    pub pinch_zoom_enabled: bool,
+    /// See [`WebView::set_browser_accelerator_keys_enabled`].
+    pub browser_accelerator_keys_enabled: bool,
+    /// See [`WebView::set_accelerator_keys_enabled`].
+    pub accelerator_keys_enabled: bool,
+}
+
+impl Default for InputPolicy {
+    fn default() -> Self {
+        Self {
+            zoom_control_enabled: true,
+            swipe_navigation_enabled: true,
+            pinch_zoom_enabled: true,
+            browser_accelerator_keys_enabled: true,
+            accelerator_keys_enabled: true,
+        }
+    }
+}
+
+/// A request for [`WebView::navigate_with_request`].
+#[derive(Clone, Debug)]
+pub struct NavigationRequest {
+    pub uri: String,
+    /// The HTTP method, e.g. `GET` or `POST`.
+    pub method: String,
+    pub headers: HashMap<String, String>,
+    pub body: Option<Vec<u8>>,
+}
+
+impl NavigationRequest {
+    /// A `GET` request for `uri` with no extra headers.
+    pub fn new(uri: &str) -> Self {
+        Self {
+            uri: String::from(uri),
+            method: String::from("GET"),
+            headers: HashMap::new(),
+            body: None,
+        }
+    }
+}
+
+/// A cookie for [`WebView::set_cookie`] and [`WebViewBuilder::with_cookie`].
+#[derive(Clone, Debug)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// The host the cookie is sent to, e.g. `example.com`, or with a leading `.` to include its
+    /// subdomains.
+    pub domain: String,
+    pub path: String,
+    /// When the cookie expires, or `None` for a session cookie.
+    pub expires: Option<SystemTime>,
+    pub http_only: bool,
+    pub secure: bool,
+}
+
+impl Cookie {
+    /// A session cookie for every path on `domain`.
+    pub fn new(name: &str, value: &str, domain: &str) -> Self {
+        Self {
+            name: String::from(name),
+            value: String::from(value),
+            domain: String::from(domain),
+            path: String::from("/"),
+            expires: None,
+            http_only: false,
+            secure: false,
+        }
+    }
+}
+
+/// The WebView2 environment of a [`WebView`], from [`WebView::environment`]. This is shared by
+/// every `WebView` created with the same user data folder and runtime.
+#[derive(Clone)]
+pub struct Environment(Arc<ICoreWebView2Environment>);
+
+impl Environment {
+    /// Get the version of the runtime this environment is using, e.g. `"94.0.992.31"`, which
+    /// may be older than [`available_browser_version`] if an update is waiting for a restart.
+    pub fn browser_version(&self) -> Result<String> {
+        let mut version = PWSTR::default();
+        unsafe { self.0.get_BrowserVersionString(&mut version) }?;
+        Ok(pwstr::take_pwstr(version))
+    }
+
+    /// Create a request which WebView2 can send, e.g. for
+    /// `ICoreWebView2_2::NavigateWithWebResourceRequest`. Requires `ICoreWebView2Environment2`.
+    pub fn create_web_resource_request(
+        &self,
+        request: &NavigationRequest,
+    ) -> Result<ICoreWebView2WebResourceRequest> {
+        let environment: ICoreWebView2Environment2 =
+            cast_interface(self.0.as_ref(), "ICoreWebView2Environment2")?;
+        let body = match request.body.as_ref() {
+            Some(body) => Some(create_mem_stream(body)?),
+            None => None,
+        };
+        Ok(unsafe {
+            environment.CreateWebResourceRequest(
+                request.uri.as_str(),
+                request.method.as_str(),
+                body,
+                format_headers(request.headers.iter()),
+            )
+        }?)
+    }
+
+    /// Create a response with `content` as the body, e.g. to answer a request without sending
+    /// it to the network.
+    pub fn create_web_resource_response(
+        &self,
+        content: Option<&[u8]>,
+        status_code: i32,
+        reason_phrase: &str,
+        headers: &[(String, String)],
+    ) -> Result<ICoreWebView2WebResourceResponse> {
+        let content = match content {
+            Some(content) => Some(create_mem_stream(content)?),
+            None => None,
+        };
+        Ok(unsafe {
+            self.0.CreateWebResourceResponse(
+                content,
+                status_code,
+                reason_phrase,
+                format_headers(headers.iter().map(|(name, value)| (name, value))),
+            )
+        }?)
+    }
+}
+
+/// The response a handler passed to [`WebView::register_virtual_host_handler`] returns.
+#[derive(Clone, Debug)]
+pub struct ResourceResponse {
+    pub status_code: i32,
+    pub reason_phrase: String,
+    pub headers: Vec<(String, String)>,
+    pub content: Vec<u8>,
+}
+
+impl ResourceResponse {
+    /// A `200 OK` response with `content` of type `content_type`, e.g. `text/html`.
+    pub fn ok(content_type: &str, content: Vec<u8>) -> Self {
+        Self {
+            status_code: 200,
+            reason_phrase: String::from("OK"),
+            headers: vec![(String::from("Content-Type"), String::from(content_type))],
+            content,
+        }
+    }
+
+    /// An empty `404 Not Found` response.
+    pub fn not_found() -> Self {
+        Self {
+            status_code: 404,
+            reason_phrase: String::from("Not Found"),
+            headers: Vec::new(),
+            content: Vec::new(),
+        }
+    }
+}
+
+/// The role of a process listed by [`WebView::process_infos`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessKind {
+    Browser,
+    Renderer,
+    Utility,
+    SandboxHelper,
+    Gpu,
+    PpapiPlugin,
+    PpapiBroker,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessInfo {
+    pub process_id: u32,
+    pub kind: ProcessKind,
+}
+
+/// An `<iframe>` reported by [`WebView::on_frame_created`].
+pub struct FrameInfo {
+    /// The `name` attribute of the `<iframe>`, which may be empty.
+    pub name: String,
+    frame: ICoreWebView2Frame,
+}
+
+impl FrameInfo {
+    /// Call `f` once the `<iframe>` is removed from the page, or the page navigates away.
+    pub fn on_destroyed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut() + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.frame.add_Destroyed(
+                callback::FrameDestroyedEventHandler::create(Box::new(move |_frame, _args| {
+                    f();
+                    Ok(())
+                })),
+                &mut token,
+            )?;
+        }
+        let frame = self.frame.clone();
+        Ok(Subscription::new(move || unsafe {
+            frame.remove_Destroyed(token)
+        }))
+    }
+
+    /// Call `f` with the new name whenever the `name` of the `<iframe>` changes.
+    pub fn on_name_changed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(String) + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.frame.add_NameChanged(
+                callback::FrameNameChangedEventHandler::create(Box::new(move |frame, _args| {
+                    if let Some(frame) = frame {
+                        let mut name = PWSTR::default();
+                        frame.get_Name(&mut name)?;
+                        f(pwstr::take_pwstr(name));
+                    }
+                    Ok(())
+                })),
+                &mut token,
+            )?;
+        }
+        let frame = self.frame.clone();
+        Ok(Subscription::new(move || unsafe {
+            frame.remove_NameChanged(token)
+        }))
+    }
+}
+
+/// A response seen by [`WebView::on_web_resource_response_received`].
+pub struct ResponseInfo {
+    /// The URI of the request this is a response to.
+    pub uri: String,
+    pub method: String,
+    pub status_code: i32,
+    pub reason_phrase: String,
+    pub headers: Vec<(String, String)>,
+    response: ICoreWebView2WebResourceResponseView,
+}
+
+impl ResponseInfo {
+    /// Fetch the body of the response and pass it to `f` once it is available. The body is
+    /// empty for responses which have none, e.g. redirects, and an error if the body was not
+    /// kept, e.g. because the response was served from the cache or was too big.
+    pub fn get_content<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(Result<Vec<u8>>) + 'static,
+    {
+        unsafe {
+            self.response.GetContent(
+                callback::WebResourceResponseViewGetContentCompletedHandler::create(Box::new(
+                    move |error_code, content| {
+                        f(error_code.map_err(Error::from).and_then(|_| match content {
+                            Some(content) => read_stream(&content),
+                            None => Ok(Vec::new()),
+                        }));
+                        Ok(())
+                    },
+                )),
+            )
+        }?;
+        Ok(())
+    }
+}
+
+/// Returned by the `on_*` event registration methods on [`WebView`]. Dropping it removes the
+/// event handler, so keep it alive for as long as the callback should run, or call
+/// [`Subscription::detach`] to leave the handler registered for the lifetime of the `WebView`.
+#[must_use = "dropping a Subscription removes the event handler"]
+pub struct Subscription(Option<Box<dyn FnOnce() -> windows::Result<()>>>);
+
+impl Subscription {
+    fn new<F>(remove: F) -> Self
+    where
+        F: FnOnce() -> windows::Result<()> + 'static,
+    {
+        Self(Some(Box::new(remove)))
+    }
+
+    /// Leave the event handler registered until the `WebView` is closed.
+    pub fn detach(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(remove) = self.0.take() {
+            let _ = remove();
+        }
+    }
+}
+
+/// Clear the `running` flag when [`WebView::run`] returns, including early returns on errors.
+struct RunningGuard(Arc<AtomicBool>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Whether [`WebView::resolve`] should fulfill or reject the promise returned to the page by a
+/// function added with [`WebView::bind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromiseStatus {
+    Resolve,
+    Reject,
+}
+
+impl PromiseStatus {
+    fn method(self) -> &'static str {
+        match self {
+            Self::Resolve => "resolve",
+            Self::Reject => "reject",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EvalResult {
+    result: Option<Value>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InvokeMessage {
+    id: u64,
+    method: String,
+    params: Vec<Value>,
+}
+
+impl WebView {
+    pub fn create(parent: Option<HWND>, debug: bool) -> Result<WebView> {
+        let mut options = WebViewBuilder::new().with_debug(debug);
+        options.parent = parent;
+        WebView::create_internal(&options)
+    }
+
+    /// Create a `WebView` which only covers `bounds` in the client area of `parent`, so that
+    /// several of them can be placed side by side in the same host window.
+    ///
+    /// Embedded `WebView`s do not subclass the parent window, so the host is responsible for
+    /// laying them out again when it resizes. Handle `WM_SIZE` in the parent's window procedure
+    /// and call [`WebView::set_bounds`] on each child with its new rectangle, e.g.:
+    ///
+    /// ```ignore
+    /// WM_SIZE => {
+    ///     let (width, height) = (loword(l_param), hiword(l_param));
+    ///     left.set_bounds(RECT { left: 0, top: 0, right: width / 2, bottom: height })?;
+    ///     right.set_bounds(RECT { left: width / 2, top: 0, right: width, bottom: height })?;
+    /// }
+    /// ```
+    pub fn create_with_bounds(parent: HWND, bounds: RECT, debug: bool) -> Result<WebView> {
+        WebView::create_internal(
+            &WebViewBuilder::new()
+                .with_parent(parent)
+                .with_bounds(bounds)
+                .with_debug(debug),
+        )
+    }
+
+    fn create_internal(options: &WebViewBuilder) -> Result<WebView> {
+        let mut options = options.clone();
+        if options.ephemeral_profile {
+            options.temp_profile = Some(Arc::new(TempProfile::new()?));
+        }
+        let options = &options;
+
+        let environment_settings = Self::environment_settings(options)?;
+        if let Some(style) = options.style {
+            if style.0 & WindowsAndMessaging::WS_CHILD.0 != 0 {
+                return Err(Error::InvalidWindowStyle);
+            }
+        }
+
+        let (parent, frame) = match options.parent {
+            Some(hwnd) => (hwnd, None),
+            None => {
+                let mut style = options
+                    .style
+                    .unwrap_or(WindowsAndMessaging::WS_OVERLAPPEDWINDOW);
+                if options.maximized {
+                    // The window is created hidden, so this only sets the size and state it has
+                    // once `run` shows it.
+                    style.0 |= WindowsAndMessaging::WS_MAXIMIZE.0;
+                }
+                let bounds = match options.monitor {
+                    Some(index) => {
+                        let work_area = monitor_work_area(index)?;
+                        let width = work_area.right - work_area.left;
+                        let height = work_area.bottom - work_area.top;
+                        Some(RECT {
+                            left: work_area.left + width / 8,
+                            top: work_area.top + height / 8,
+                            right: work_area.right - width / 8,
+                            bottom: work_area.bottom - height / 8,
+                        })
+                    }
+                    None => None,
+                };
+                let frame = FrameWindow::new(
+                    options.class_name.as_deref().unwrap_or("WebView"),
+                    style,
+                    options.ex_style.unwrap_or_default(),
+                    bounds,
+                );
+                (*frame.window, Some(frame))
+            }
+        };
+
+        Self::create_in(options, parent, frame, environment_settings, None)
+    }
+
+    /// Check the options for the WebView2 environment and get the user data folder and browser
+    /// executable folder to create it with.
+    fn environment_settings(options: &WebViewBuilder) -> Result<EnvironmentSettings> {
+        let user_data_folder = match (options.temp_profile.as_ref(), options.profile_name.as_ref())
+        {
+            (Some(temp_profile), _) => Some(temp_profile.0.to_string_lossy().into_owned()),
+            (None, Some(name)) => Some(profile_user_data_folder(name)?),
+            (None, None) => None,
+        };
+        let browser_executable_folder = match options.browser_executable_folder.as_ref() {
+            Some(folder) => {
+                if !Path::new(folder).join("msedgewebview2.exe").is_file() {
+                    return Err(Error::InvalidBrowserExecutableFolder(folder.clone()));
+                }
+                Some(folder.clone())
+            }
+            None => None,
+        };
+        let environment_options = match options.language.as_ref() {
+            Some(language) => {
+                if !is_language_tag(language) {
+                    return Err(Error::InvalidLanguageTag(language.clone()));
+                }
+                Some(options::EnvironmentOptions::create(language))
+            }
+            None => None,
+        };
+        Ok((
+            browser_executable_folder,
+            user_data_folder,
+            environment_options,
+        ))
+    }
+
+    /// Create the environment and controller in `parent`, which is the frame window if there
+    /// is one. With `previous`, e.g. in [`WebView::recover`], the new `WebView` shares its
+    /// message queue, bindings and other state which does not belong to the controller.
+    fn create_in(
+        options: &WebViewBuilder,
+        parent: HWND,
+        frame: Option<FrameWindow>,
+        (browser_executable_folder, user_data_folder, environment_options): EnvironmentSettings,
+        previous: Option<&WebView>,
+    ) -> Result<WebView> {
+        let timeout = options.timeout.unwrap_or(DEFAULT_TIMEOUT);
+        let environment = callback::CreateCoreWebView2EnvironmentCompletedHandler::run_async(
+            timeout,
+            |environmentcreatedhandler| unsafe {
+                match (
+                    browser_executable_folder,
+                    user_data_folder,
+                    environment_options,
+                ) {
+                    (None, None, None) => CreateCoreWebView2Environment(environmentcreatedhandler),
+                    (browser_executable_folder, user_data_folder, environment_options) => {
+                        CreateCoreWebView2EnvironmentWithOptions(
+                            browser_executable_folder.unwrap_or_default(),
+                            user_data_folder.unwrap_or_default(),
+                            environment_options,
+                            environmentcreatedhandler,
+                        )
+                    }
+                }
+                .map_err(environment_error)
+            },
+            |error_code, environment| {
+                logger::log(format_args!("environment created: {:?}", error_code));
+                error_code.map_err(environment_error)?;
+                environment.ok_or_else(|| windows::Error::fast_error(E_POINTER).into())
+            },
+        )?;
+
+        let controller = callback::CreateCoreWebView2ControllerCompletedHandler::run_async(
+            timeout,
+            |handler| unsafe {
+                environment
+                    .CreateCoreWebView2Controller(parent, handler)
+                    .map_err(Error::WindowsError)
+            },
+            |error_code, controller| {
+                logger::log(format_args!("controller created: {:?}", error_code));
+                error_code?;
+                controller.ok_or_else(|| windows::Error::fast_error(E_POINTER).into())
+            },
+        )?;
+
+        let size = get_window_size(parent);
+        unsafe {
+            controller.put_Bounds(options.bounds.unwrap_or(RECT {
+                left: 0,
+                top: 0,
+                right: size.cx,
+                bottom: size.cy,
+            }))?;
+            controller.put_IsVisible(true)?;
+        }
+
+        let webview = unsafe { controller.get_CoreWebView2()? };
+        // Every settings toggle uses the same settings object, which belongs to this controller,
+        // so a recreated `WebView` fetches its own.
+        let settings = unsafe { webview.get_Settings()? };
+
+        if !options.debug {
+            unsafe {
+                settings.put_AreDefaultContextMenusEnabled(false)?;
+                settings.put_AreDevToolsEnabled(false)?;
+            }
+        }
+
+        if let Some(frame) = frame.as_ref() {
+            *frame.size.lock()? = size;
+        }
+
+        let webview = match previous {
+            Some(previous) => WebViewInner {
+                environment: Arc::new(environment),
+                controller: Arc::new(WebViewController(controller, options.temp_profile.clone())),
+                webview: Arc::new(webview),
+                settings: Arc::new(settings),
+                frame,
+                web_message_received: Arc::new(Mutex::new(None)),
+                accelerator_keys_blocker: Arc::new(Mutex::new(None)),
+                mute_script: Arc::new(Mutex::new(None)),
+                no_swipe_script: Arc::new(Mutex::new(None)),
+                fetch_users: Arc::new(Mutex::new(0)),
+                options: Arc::new(options.clone()),
+                ..(*previous.inner).clone()
+            },
+            None => {
+                let (tx, rx) = mpsc::channel();
+                let terminated = Arc::new(AtomicBool::new(false));
+                let quit_on_drop = frame.as_ref().map(|frame| {
+                    Arc::new(QuitOnDrop {
+                        window: *frame.window,
+                        terminated: terminated.clone(),
+                    })
+                });
+
+                WebViewInner {
+                    environment: Arc::new(environment),
+                    controller: Arc::new(WebViewController(
+                        controller,
+                        options.temp_profile.clone(),
+                    )),
+                    webview: Arc::new(webview),
+                    settings: Arc::new(settings),
+                    tx,
+                    rx: Arc::new(rx),
+                    dispatch_event: Arc::new(DispatchEvent::new()?),
+                    thread_id: unsafe { Threading::GetCurrentThreadId() },
+                    bindings: Arc::new(Mutex::new(HashMap::new())),
+                    web_message_allowed_origins: Arc::new(Mutex::new(Vec::new())),
+                    frame,
+                    parent: Arc::new(parent),
+                    url: Arc::new(Mutex::new(String::new())),
+                    title: Arc::new(Mutex::new(String::new())),
+                    running: Arc::new(AtomicBool::new(false)),
+                    timeout,
+                    web_message_received: Arc::new(Mutex::new(None)),
+                    accelerator_keys_blocker: Arc::new(Mutex::new(None)),
+                    loaded_html: Arc::new(Mutex::new(None)),
+                    mute_script: Arc::new(Mutex::new(None)),
+                    no_swipe_script: Arc::new(Mutex::new(None)),
+                    init_scripts: Arc::new(Mutex::new(Vec::new())),
+                    fetch_users: Arc::new(Mutex::new(0)),
+                    options: Arc::new(options.clone()),
+                    focus_contents_on_run: !options.no_focus_contents_on_run,
+                    terminated,
+                    quit_on_drop,
+                }
+            }
+        };
+        let webview = WebView {
+            inner: Arc::new(webview),
+        };
+
+        // Inject the invoke handler.
+        webview.add_script(
+            r#"window.external = { invoke: s => window.chrome.webview.postMessage(s) };"#,
+        )?;
+
+        let bindings = webview.bindings.clone();
+        let allowed_origins = webview.web_message_allowed_origins.clone();
+        // The handler belongs to the controller, so a strong clone here would keep the `WebView`
+        // alive forever, see `WeakWebView`.
+        let weak = webview.downgrade();
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            webview.webview.add_WebMessageReceived(
+                callback::WebMessageReceivedEventHandler::create(Box::new(
+                    move |_webview, args| {
+                        let bound = match weak.upgrade() {
+                            Some(bound) => bound,
+                            None => return Ok(()),
+                        };
+                        if let Some(args) = args {
+                            if !is_allowed_source(&allowed_origins, &args)? {
+                                return Ok(());
+                            }
+                            let mut message = PWSTR::default();
+                            if args.get_WebMessageAsJson(&mut message).is_ok() {
+                                let message = pwstr::take_pwstr(message);
+                                logger::log(format_args!("web message received: {}", message));
+                                if let Ok(value) = serde_json::from_str::<InvokeMessage>(&message) {
+                                    // Take the binding out of the map while it runs, so it
+                                    // can call back into the WebView, e.g. to bind something
+                                    // else, without deadlocking on the map.
+                                    let f = match bindings.lock() {
+                                        Ok(mut bindings) => bindings.remove(&value.method),
+                                        Err(_) => None,
+                                    };
+                                    if let Some(mut f) = f {
+                                        // Unwinding across the COM callback is undefined
+                                        // behavior, so turn a panic into a rejected promise.
+                                        let params = value.params;
+                                        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                                            (*f)(&bound, params)
+                                        }));
+                                        if let Ok(mut bindings) = bindings.lock() {
+                                            bindings.entry(value.method.clone()).or_insert(f);
+                                        }
+                                        match result {
+                                            Ok(Ok(result)) => bound.resolve(
+                                                value.id,
+                                                PromiseStatus::Resolve,
+                                                result,
+                                            ),
+                                            Ok(Err(err)) => bound.resolve(
+                                                value.id,
+                                                PromiseStatus::Reject,
+                                                Value::String(format!("{:#?}", err)),
+                                            ),
+                                            Err(_) => {
+                                                logger::log(format_args!(
+                                                    "binding panicked: {}",
+                                                    value.method
+                                                ));
+                                                bound.resolve(
+                                                    value.id,
+                                                    PromiseStatus::Reject,
+                                                    Value::String(String::from("internal error")),
+                                                )
+                                            }
+                                        }
+                                        .unwrap();
+                                    } else {
+                                        // The binding is still running further up the stack,
+                                        // e.g. it pumps messages while it waits for a script, or
+                                        // it has been removed since the page called it.
+                                        bound
+                                            .resolve(
+                                                value.id,
+                                                PromiseStatus::Reject,
+                                                Value::String(format!(
+                                                    "{} is already running or no longer bound",
+                                                    value.method
+                                                )),
+                                            )
+                                            .unwrap();
+                                    }
+                                }
+                            }
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+
+        // Keep the invoke handler registered for as long as the WebView is alive.
+        let remove_webview = webview.webview.clone();
+        *webview.web_message_received.lock()? = Some(Subscription::new(move || unsafe {
+            remove_webview.remove_WebMessageReceived(token)
+        }));
+
+        if options.muted {
+            webview.set_muted(true)?;
+        }
+
+        // The init script from `set_muted` only sees media which starts playing after it runs,
+        // so mute whatever each new document already has once it has loaded as well.
+        let weak = webview.downgrade();
+        webview
+            .on_navigation_completed(move |_is_success, _web_error_status| {
+                if let Some(webview) = weak.upgrade() {
+                    if let Ok(true) = webview.is_muted() {
+                        let _ = webview.eval(MUTE_MEDIA_SCRIPT);
+                    }
+                }
+            })?
+            .detach();
+
+        for cookie in options.cookies.iter() {
+            webview.set_cookie(cookie)?;
+        }
+
+        if let (Some(frame), false) = (webview.frame.clone(), options.no_auto_fullscreen) {
+            webview
+                .on_contains_fullscreen_element_changed(move |contains| {
+                    // There's no way to report an error from the event handler, and the window
+                    // just stays as it is if this fails.
+                    let _ = frame.set_fullscreen(contains);
+                })?
+                .detach();
+        }
+
+        if webview.frame.is_some() {
+            WebView::set_window_webview(parent, Some(Box::new(webview.downgrade())));
+        }
+
+        Ok(webview)
+    }
+
+    /// Navigate to the URL passed to [`WebView::navigate`], show the frame window and run the
+    /// message loop until the window is closed or [`WebView::terminate`] is called.
+    ///
+    /// This must be called on the thread which created the `WebView`, and only once at a time,
+    /// otherwise it returns [`Error::WrongThread`] or [`Error::AlreadyRunning`]. While the app
+    /// keeps other clones of the `WebView`, the loop does not keep it alive by itself, so it also
+    /// returns once the app has dropped all of them.
+    pub fn run(self) -> Result<()> {
+        if unsafe { Threading::GetCurrentThreadId() } != self.thread_id {
+            return Err(Error::WrongThread);
+        }
+
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Err(Error::AlreadyRunning);
+        }
+        let _running = RunningGuard(self.running.clone());
+
+        let webview = self.webview.as_ref();
+        let url = self.url.try_lock()?.clone();
+        let (tx, rx) = mpsc::channel();
+
+        if !url.is_empty() {
+            let handler = callback::NavigationCompletedEventHandler::create(Box::new(
+                move |_sender, _args| {
+                    logger::log(format_args!("navigation completed"));
+                    tx.send(()).expect("send over mpsc channel");
+                    Ok(())
+                },
+            ));
+            let mut token = EventRegistrationToken::default();
+            unsafe {
+                webview.add_NavigationCompleted(handler, &mut token)?;
+                logger::log(format_args!("navigation started: {}", url));
+                webview.Navigate(url)?;
+                let result = wait_with_pump(rx, self.timeout);
+                webview.remove_NavigationCompleted(token)?;
+                result?;
+            }
+        }
+
+        if let Some(frame) = self.frame.as_ref() {
+            let hwnd = *frame.window;
+            unsafe {
+                WindowsAndMessaging::ShowWindow(hwnd, WindowsAndMessaging::SW_SHOW);
+                Gdi::UpdateWindow(hwnd);
+                KeyboardAndMouseInput::SetFocus(hwnd);
+            }
+
+            if self.focus_contents_on_run {
+                self.focus_contents()?;
+            }
+        }
+
+        let weak = self.downgrade();
+        let rx = self.rx.clone();
+        let dispatch_event = self.dispatch_event.clone();
+        let _owned = if Arc::strong_count(&self.inner) == 1 {
+            Some(self)
+        } else {
+            drop(self);
+            None
+        };
+
+        loop {
+            while let Ok(f) = rx.try_recv() {
+                match weak.upgrade() {
+                    Some(webview) => (f)(webview),
+                    None => return Ok(()),
+                }
+            }
+
+            if !wait_for_dispatch(&dispatch_event, INFINITE)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Process any closures queued with [`WebView::dispatch`] and all of the pending window
+    /// messages without blocking, for hosts which already own the message loop, e.g. calling
+    /// this once per frame in a game loop. Returns `false` once `WM_QUIT` has been received.
+    /// [`MessagePump`] wraps this for GUI frameworks.
+    ///
+    /// Unlike [`WebView::run`], this does not navigate to the URL passed to
+    /// [`WebView::navigate`] or show the frame window.
+    pub fn pump_messages(&self) -> Result<bool> {
+        while let Ok(f) = self.rx.try_recv() {
+            (f)(self.clone());
+        }
+
+        let mut msg = MSG::default();
+        let h_wnd = HWND::default();
+
+        unsafe {
+            while WindowsAndMessaging::PeekMessageA(
+                &mut msg,
+                h_wnd,
+                0,
+                0,
+                WindowsAndMessaging::PM_REMOVE,
+            )
+            .as_bool()
+            {
+                match msg.message {
+                    WindowsAndMessaging::WM_QUIT => return Ok(false),
+                    WindowsAndMessaging::WM_APP => {
+                        while let Ok(f) = self.rx.try_recv() {
+                            (f)(self.clone());
+                        }
+                    }
+                    _ => {
+                        WindowsAndMessaging::TranslateMessage(&msg);
+                        WindowsAndMessaging::DispatchMessageA(&msg);
+                    }
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub fn terminate(self) -> Result<()> {
+        self.terminated.store(true, Ordering::SeqCst);
+        self.dispatch(|_webview| unsafe {
+            WindowsAndMessaging::PostQuitMessage(0);
+        })?;
+
+        if self.frame.is_some() {
+            WebView::set_window_webview(self.get_window(), None);
+        }
+
+        Ok(())
+    }
+
+    /// Replace a `WebView` whose browser or renderer process failed with a new one in the same
+    /// window, e.g. after the renderer crashed and left the page blank, and return it. The new
+    /// `WebView` keeps the frame window, bindings, init scripts, allowed web message origins,
+    /// title, mute state and whether swipe navigation and accelerator keys are enabled, and
+    /// navigates to the URI the old one was showing.
+    ///
+    /// The old `WebView` and all of its clones are dead afterwards: they still refer to the old
+    /// controller, which is hidden but only closed once they are dropped, so replace every copy
+    /// the app kept, e.g. a [`WeakWebView`], with the returned one. Handlers registered with
+    /// `on_*` methods and [`WebView::add_host_object`] belonged to the old controller, so they
+    /// need to be registered again. [`WebViewHandle`]s keep working because the queue is shared,
+    /// but while [`WebView::run`] is still running for the old `WebView`, it passes that one to
+    /// the closures, so they should use the `WebView` the app stored instead.
+    pub fn recover(&self) -> Result<WebView> {
+        let url = match self.source() {
+            Ok(url) if !url.is_empty() => url,
+            _ => self.url.lock()?.clone(),
+        };
+        let muted = self.is_muted()?;
+        let swipe_navigation_enabled = self.no_swipe_script.lock()?.is_none();
+        let accelerator_keys_enabled = self.accelerator_keys_blocker.lock()?.is_none();
+
+        let mut options = (*self.options).clone();
+        options.muted = false;
+        // The cookies are still in the profile, possibly updated by the page since.
+        options.cookies.clear();
+        if self.frame.is_none() {
+            let mut bounds = RECT::default();
+            unsafe { self.controller.0.get_Bounds(&mut bounds) }?;
+            options.bounds = Some(bounds);
+        }
+        unsafe { self.controller.0.put_IsVisible(false) }?;
+
+        let environment_settings = Self::environment_settings(&options)?;
+        let webview = Self::create_in(
+            &options,
+            *self.parent,
+            self.frame.clone(),
+            environment_settings,
+            Some(self),
+        )?;
+
+        let init_scripts = mem::take(&mut *webview.init_scripts.lock()?);
+        for (_, js) in init_scripts {
+            webview.init(&js)?;
+        }
+        webview.set_muted(muted)?;
+        webview.set_swipe_navigation_enabled(swipe_navigation_enabled)?;
+        webview.set_accelerator_keys_enabled(accelerator_keys_enabled)?;
+
+        if !url.is_empty() {
+            *webview.url.lock()? = url.clone();
+            unsafe { webview.webview.Navigate(url) }?;
+        }
+        Ok(webview)
+    }
+
+    /// Set the caption of the frame window. Embedded `WebView`s have no caption of their own,
+    /// so this only remembers the title for [`WebView::title`].
+    pub fn set_title(&self, title: &str) -> Result<&Self> {
+        *self.title.lock()? = String::from(title);
+        if let Some(frame) = self.frame.as_ref() {
+            unsafe {
+                WindowsAndMessaging::SetWindowTextA(*frame.window, title);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Get the title passed to [`WebView::set_title`], or the `<title>` of the current document
+    /// if it has not been set, e.g. for embedded `WebView`s.
+    pub fn title(&self) -> Result<String> {
+        let title = self.title.lock()?.clone();
+        if !title.is_empty() {
+            return Ok(title);
+        }
+
+        let mut title = PWSTR::default();
+        unsafe { self.webview.get_DocumentTitle(&mut title) }?;
+        Ok(pwstr::take_pwstr(title))
+    }
+
+    pub fn set_size(&self, width: i32, height: i32) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            *frame.size.lock()? = SIZE {
+                cx: width,
+                cy: height,
+            };
+            unsafe {
+                self.controller.0.put_Bounds(client_bounds(
+                    &self.controller.0,
+                    SIZE {
+                        cx: width,
+                        cy: height,
+                    },
+                ))?;
+
+                WindowsAndMessaging::SetWindowPos(
+                    *frame.window,
+                    None,
+                    0,
+                    0,
+                    width,
+                    height,
+                    WindowsAndMessaging::SWP_NOACTIVATE
+                        | WindowsAndMessaging::SWP_NOZORDER
+                        | WindowsAndMessaging::SWP_NOMOVE,
+                );
+            }
+        }
+        Ok(self)
+    }
+
+    /// Move the top left corner of the frame window, including its border and caption, to `x`,
+    /// `y` in screen coordinates, keeping its size, e.g. to restore a saved position together
+    /// with [`WebView::set_size`]. This does nothing for an embedded `WebView`.
+    pub fn set_position(&self, x: i32, y: i32) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            unsafe {
+                WindowsAndMessaging::SetWindowPos(
+                    *frame.window,
+                    None,
+                    x,
+                    y,
+                    0,
+                    0,
+                    WindowsAndMessaging::SWP_NOACTIVATE
+                        | WindowsAndMessaging::SWP_NOZORDER
+                        | WindowsAndMessaging::SWP_NOSIZE,
+                );
+            }
+        }
+        Ok(self)
+    }
+
+    /// Keep the user from resizing the frame window smaller than `width` by `height`, including
+    /// the borders and title bar like [`WebView::set_size`]. This does nothing for an embedded
+    /// `WebView`.
+    pub fn set_min_size(&self, width: i32, height: i32) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            *frame.min_size.lock()? = Some(SIZE {
+                cx: width,
+                cy: height,
+            });
+        }
+        Ok(self)
+    }
+
+    /// Keep the user from resizing the frame window larger than `width` by `height`, including
+    /// the borders and title bar like [`WebView::set_size`]. This also limits the maximized size.
+    /// This does nothing for an embedded `WebView`.
+    pub fn set_max_size(&self, width: i32, height: i32) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            *frame.max_size.lock()? = Some(SIZE {
+                cx: width,
+                cy: height,
+            });
+        }
+        Ok(self)
+    }
+
+    /// Load an `.ico` file and use it for both the small (title bar) and big (Alt+Tab) icons of
+    /// the frame window. This does nothing for an embedded `WebView`.
+    pub fn set_icon_from_file(&self, path: &str) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            if !std::path::Path::new(path).is_file() {
+                return Err(Error::IconError(format!("icon file not found: {}", path)));
+            }
+
+            let mut path: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
+            frame.set_icon(
+                HINSTANCE::default(),
+                PWSTR(path.as_mut_ptr()),
+                WindowsAndMessaging::LR_LOADFROMFILE,
+            )?;
+        }
+        Ok(self)
+    }
+
+    /// Load an icon resource linked into the current module by its integer ID and use it for
+    /// both the small and big icons of the frame window. This does nothing for an embedded
+    /// `WebView`.
+    pub fn set_icon_from_resource(&self, id: u16) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            let instance = unsafe { LibraryLoader::GetModuleHandleA(None) };
+            frame.set_icon(
+                instance,
+                // MAKEINTRESOURCEW
+                PWSTR(id as usize as *mut _),
+                WindowsAndMessaging::LR_DEFAULTCOLOR,
+            )?;
+        }
+        Ok(self)
+    }
+
+    /// Keep the frame window above all non-topmost windows, or return it to the normal z-order.
+    /// [`WebView::set_size`] passes `SWP_NOZORDER`, so resizing does not undo this. This does
+    /// nothing for an embedded `WebView`.
+    pub fn set_always_on_top(&self, on: bool) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            unsafe {
+                WindowsAndMessaging::SetWindowPos(
+                    *frame.window,
+                    if on {
+                        WindowsAndMessaging::HWND_TOPMOST
+                    } else {
+                        WindowsAndMessaging::HWND_NOTOPMOST
+                    },
+                    0,
+                    0,
+                    0,
+                    0,
+                    WindowsAndMessaging::SWP_NOMOVE
+                        | WindowsAndMessaging::SWP_NOSIZE
+                        | WindowsAndMessaging::SWP_NOACTIVATE,
+                );
+            }
+        }
+        Ok(self)
+    }
+
+    /// Make the frame window cover the whole monitor it is on without a border or caption, or
+    /// restore the style, position and maximized state it had before. This does nothing for an
+    /// embedded `WebView`.
+    ///
+    /// By default, the frame window also does this while an element in the page is fullscreen,
+    /// see [`WebViewBuilder::with_auto_fullscreen`].
+    pub fn set_fullscreen(&self, fullscreen: bool) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            frame.set_fullscreen(fullscreen)?;
+        }
+        Ok(self)
+    }
+
+    /// Check whether the frame window is fullscreen, see [`WebView::set_fullscreen`]. Embedded
+    /// `WebView`s never are.
+    pub fn is_fullscreen(&self) -> Result<bool> {
+        Ok(match self.frame.as_ref() {
+            Some(frame) => frame.windowed.lock()?.is_some(),
+            None => false,
+        })
+    }
+
+    /// Let the user resize and maximize the frame window, which is the default for
+    /// `WS_OVERLAPPEDWINDOW`, or keep it at the size the app sets with [`WebView::set_size`] by
+    /// removing the sizing border and disabling the maximize button. The limits from
+    /// [`WebView::set_min_size`] and [`WebView::set_max_size`] still apply while it is resizable.
+    /// This does nothing for an embedded `WebView`.
+    pub fn set_resizable(&self, resizable: bool) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            let window = *frame.window;
+            let resize_style =
+                WindowsAndMessaging::WS_THICKFRAME.0 | WindowsAndMessaging::WS_MAXIMIZEBOX.0;
+            unsafe {
+                let style = GetWindowLong(window, WindowsAndMessaging::GWL_STYLE) as u32;
+                let style = if resizable {
+                    style | resize_style
+                } else {
+                    style & !resize_style
+                };
+                SetWindowLong(window, WindowsAndMessaging::GWL_STYLE, style as isize);
+
+                // Windows caches the frame, so it only redraws the border and caption buttons
+                // once it is told the style changed.
+                WindowsAndMessaging::SetWindowPos(
+                    window,
+                    None,
+                    0,
+                    0,
+                    0,
+                    0,
+                    WindowsAndMessaging::SWP_FRAMECHANGED
+                        | WindowsAndMessaging::SWP_NOMOVE
+                        | WindowsAndMessaging::SWP_NOSIZE
+                        | WindowsAndMessaging::SWP_NOZORDER
+                        | WindowsAndMessaging::SWP_NOACTIVATE,
+                );
+            }
+        }
+        Ok(self)
+    }
+
+    /// Restore the frame window if it is minimized, and bring it to the foreground. This does
+    /// nothing for an embedded `WebView`.
+    ///
+    /// Windows only lets a process take the foreground from another one in some cases, e.g. if it
+    /// received the last input event, or if the foreground process called
+    /// `AllowSetForegroundWindow` for it. This attaches to the input queue of the thread which owns
+    /// the foreground window while it asks, which usually gets around that, but when the request
+    /// is still denied, the taskbar button flashes instead.
+    pub fn bring_to_front(&self) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            let window = *frame.window;
+            unsafe {
+                if WindowsAndMessaging::IsIconic(window).as_bool() {
+                    WindowsAndMessaging::ShowWindow(window, WindowsAndMessaging::SW_RESTORE);
+                }
+
+                let foreground_thread_id = WindowsAndMessaging::GetWindowThreadProcessId(
+                    WindowsAndMessaging::GetForegroundWindow(),
+                    ptr::null_mut(),
+                );
+                let thread_id = Threading::GetCurrentThreadId();
+                let attached = foreground_thread_id != 0
+                    && foreground_thread_id != thread_id
+                    && Threading::AttachThreadInput(thread_id, foreground_thread_id, true)
+                        .as_bool();
+
+                WindowsAndMessaging::BringWindowToTop(window);
+                WindowsAndMessaging::SetForegroundWindow(window);
+
+                if attached {
+                    Threading::AttachThreadInput(thread_id, foreground_thread_id, false);
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn minimize(&self) -> Result<&Self> {
+        self.show_window(WindowsAndMessaging::SW_MINIMIZE)
+    }
+
+    pub fn maximize(&self) -> Result<&Self> {
+        self.show_window(WindowsAndMessaging::SW_MAXIMIZE)
+    }
+
+    pub fn restore(&self) -> Result<&Self> {
+        self.show_window(WindowsAndMessaging::SW_RESTORE)
+    }
+
+    pub fn show(&self) -> Result<&Self> {
+        self.show_window(WindowsAndMessaging::SW_SHOW)
+    }
+
+    /// Hide the frame window. The message loop in [`WebView::run`] keeps running until the window
+    /// is closed or [`WebView::terminate`] is called, so it can be shown again later.
+    pub fn hide(&self) -> Result<&Self> {
+        self.show_window(WindowsAndMessaging::SW_HIDE)
+    }
+
+    /// Call `ShowWindow` on the frame window. This does nothing for an embedded `WebView`.
+    fn show_window(&self, command: WindowsAndMessaging::SHOW_WINDOW_CMD) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            unsafe {
+                WindowsAndMessaging::ShowWindow(*frame.window, command);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Fill the parts of the frame window which the `WebView` has not painted yet with `argb`, as
+    /// `0xAARRGGBB` like [`WebView::set_default_background_color`], ignoring the alpha. This does
+    /// nothing for an embedded `WebView`.
+    ///
+    /// While the user drags the border to make the window bigger, Windows shows the new area
+    /// right away, but the `WebView` only catches up a frame or two later. Without a color, the
+    /// frame window does not erase its background at all, which avoids flashing the default
+    /// white window background over a dark page, but leaves whatever was there before in the new
+    /// area for a moment. Using the background color of the page here, and for
+    /// [`WebView::set_default_background_color`], makes that gap blend in. Resizing the `WebView`
+    /// only once the drag ends would avoid the gap entirely, but the page would stay at its old
+    /// size until then, so the frame window resizes it in `WM_SIZE` as the drag goes on.
+    pub fn set_background_color(&self, argb: u32) -> Result<&Self> {
+        if let Some(frame) = self.frame.as_ref() {
+            *frame.background_color.lock()? = Some(argb);
+            unsafe { Gdi::InvalidateRect(*frame.window, ptr::null(), true) };
+        }
+        Ok(self)
+    }
+
+    /// Call `f` when the user asks to close the frame window, e.g. with the close button or
+    /// Alt+F4. Returning `false` cancels the close so the app can prompt first or hide to the
+    /// tray instead, and returning `true` destroys the window. Without a callback the window is
+    /// always destroyed. This does nothing for an embedded `WebView`.
+    pub fn on_close_requested<F>(&self, f: F) -> Result<&Self>
+    where
+        F: FnMut() -> bool + 'static,
+    {
+        if let Some(frame) = self.frame.as_ref() {
+            *frame.close_requested.lock()? = Some(Box::new(f));
+        }
+        Ok(self)
+    }
+
+    /// Move and resize the `WebView` within its parent window, in client coordinates. Owned frame
+    /// windows already do this for the whole client area in `WM_SIZE`, so this is mostly useful
+    /// for embedded `WebView`s created with [`WebView::create_with_bounds`].
+    ///
+    /// By default the bounds are in physical pixels, the same as `GetClientRect` returns for a
+    /// per-monitor DPI aware process. See [`WebView::set_bounds_mode`] to give them in DIPs.
+    pub fn set_bounds(&self, bounds: RECT) -> Result<&Self> {
+        unsafe { self.controller.0.put_Bounds(bounds) }?;
+        Ok(self)
+    }
+
+    /// Same as [`WebView::set_bounds`], but also set the zoom factor in a single step, so the
+    /// page is only laid out once, e.g. when scaling a thumbnail of the page.
+    pub fn set_bounds_and_zoom(&self, bounds: RECT, zoom_factor: f64) -> Result<&Self> {
+        unsafe {
+            self.controller
+                .0
+                .SetBoundsAndZoomFactor(bounds, zoom_factor)
+        }?;
+        Ok(self)
+    }
+
+    /// Choose whether [`WebView::set_bounds`] takes physical pixels, which is the default, or
+    /// DIPs which are multiplied by the rasterization scale, e.g. for hosts which lay out their
+    /// children in DIPs. See [`WebView::set_rasterization_scale`] for how the scale is chosen.
+    /// Requires `ICoreWebView2Controller3`.
+    ///
+    /// In [`BoundsMode::RasterizationScale`], changing the scale with
+    /// [`WebView::set_rasterization_scale`] or by moving to another monitor also changes the size
+    /// of the `WebView` in physical pixels, so embedded `WebView`s need to call
+    /// [`WebView::set_bounds`] again, e.g. from [`WebView::on_rasterization_scale_changed`].
+    /// Owned frame windows convert their client area to DIPs and keep filling it either way.
+    pub fn set_bounds_mode(&self, mode: BoundsMode) -> Result<&Self> {
+        let controller: ICoreWebView2Controller3 =
+            cast_interface(&self.controller.0, "ICoreWebView2Controller3")?;
+        unsafe { controller.put_BoundsMode(mode.into()) }?;
+        self.update_frame_bounds()?;
+        Ok(self)
+    }
+
+    /// Get the units [`WebView::set_bounds`] takes, see [`WebView::set_bounds_mode`]. Requires
+    /// `ICoreWebView2Controller3`.
+    pub fn bounds_mode(&self) -> Result<BoundsMode> {
+        let controller: ICoreWebView2Controller3 =
+            cast_interface(&self.controller.0, "ICoreWebView2Controller3")?;
+        let mut mode = COREWEBVIEW2_BOUNDS_MODE::default();
+        unsafe { controller.get_BoundsMode(&mut mode) }?;
+        Ok(
+            if mode == COREWEBVIEW2_BOUNDS_MODE_USE_RASTERIZATION_SCALE {
+                BoundsMode::RasterizationScale
+            } else {
+                BoundsMode::RawPixels
+            },
+        )
+    }
+
+    /// Make the `WebView` fill the client area of the frame window again, e.g. after the bounds
+    /// mode or rasterization scale changed. This does nothing for an embedded `WebView`.
+    fn update_frame_bounds(&self) -> Result<()> {
+        if let Some(frame) = self.frame.as_ref() {
+            let size = get_window_size(*frame.window);
+            unsafe {
+                self.controller
+                    .0
+                    .put_Bounds(client_bounds(&self.controller.0, size))
+            }?;
+        }
+        Ok(())
+    }
+
+    /// Call `f` for keys which the browser treats as accelerators, e.g. F5, Ctrl+P or keys
+    /// pressed with Alt. Returning `true` marks the key as handled and suppresses the default
+    /// browser behavior, while returning `false` lets it through.
+    pub fn on_accelerator_key_pressed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(AcceleratorKey) -> bool + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.controller.0.add_AcceleratorKeyPressed(
+                callback::AcceleratorKeyPressedEventHandler::create(Box::new(
+                    move |_controller, args| {
+                        if let Some(args) = args {
+                            let mut kind = COREWEBVIEW2_KEY_EVENT_KIND::default();
+                            let mut virtual_key = 0;
+                            args.get_KeyEventKind(&mut kind)?;
+                            args.get_VirtualKey(&mut virtual_key)?;
+
+                            let key = AcceleratorKey {
+                                virtual_key,
+                                key_down: kind == COREWEBVIEW2_KEY_EVENT_KIND_KEY_DOWN
+                                    || kind == COREWEBVIEW2_KEY_EVENT_KIND_SYSTEM_KEY_DOWN,
+                                control: is_key_down(KeyboardAndMouseInput::VK_CONTROL as i32),
+                                shift: is_key_down(KeyboardAndMouseInput::VK_SHIFT as i32),
+                                alt: is_key_down(KeyboardAndMouseInput::VK_MENU as i32),
+                            };
+
+                            if f(key) {
+                                args.put_Handled(true)?;
+                            }
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+        let controller = self.controller.clone();
+        Ok(Subscription::new(move || unsafe {
+            controller.0.remove_AcceleratorKeyPressed(token)
+        }))
+    }
+
+    /// Enable or disable all of the browser specific accelerator keys, e.g. Ctrl+F, Ctrl+P or
+    /// F5, at once. Keys which are not browser specific, e.g. Ctrl+C, are not affected.
+    pub fn set_browser_accelerator_keys_enabled(&self, enabled: bool) -> Result<&Self> {
+        let settings: ICoreWebView2Settings3 =
+            cast_interface(self.settings.as_ref(), "ICoreWebView2Settings3")?;
+        unsafe { settings.put_AreBrowserAcceleratorKeysEnabled(enabled) }?;
+        Ok(self)
+    }
+
+    /// Enable or disable every key the browser treats as an accelerator, which are enabled by
+    /// default. Unlike [`WebView::set_browser_accelerator_keys_enabled`], this also stops keys
+    /// such as Ctrl+C, Ctrl+V and Alt+F4 from reaching the page, e.g. for a kiosk. Handlers
+    /// registered with [`WebView::on_accelerator_key_pressed`] still see the keys.
+    pub fn set_accelerator_keys_enabled(&self, enabled: bool) -> Result<&Self> {
+        let blocker = if enabled {
+            None
+        } else {
+            Some(self.on_accelerator_key_pressed(|_| true)?)
+        };
+        // Drop the old subscription after releasing the lock.
+        let _previous = mem::replace(&mut *self.accelerator_keys_blocker.lock()?, blocker);
+        Ok(self)
+    }
+
+    /// Apply every setting in `policy` at once, e.g. to lock down a kiosk. Settings which the
+    /// runtime is too old for, e.g. pinch zoom before `ICoreWebView2Settings5`, are skipped and
+    /// logged instead of failing, so the rest still apply.
+    pub fn set_input_policy(&self, policy: InputPolicy) -> Result<&Self> {
+        let skip_unsupported = |result: Result<&Self>| match result {
+            Err(Error::UnsupportedInterface(name)) => {
+                logger::log(format_args!("input policy needs {}, skipping", name));
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        };
+
+        unsafe {
+            self.settings
+                .put_IsZoomControlEnabled(policy.zoom_control_enabled)
+        }?;
+        self.set_swipe_navigation_enabled(policy.swipe_navigation_enabled)?;
+        skip_unsupported(self.set_pinch_zoom_enabled(policy.pinch_zoom_enabled))?;
+        skip_unsupported(
+            self.set_browser_accelerator_keys_enabled(policy.browser_accelerator_keys_enabled),
+        )?;
+        self.set_accelerator_keys_enabled(policy.accelerator_keys_enabled)?;
+        Ok(self)
+    }
+
+    /// Open a print dialog for the current page, e.g. from a host button after turning off Ctrl+P
+    /// with [`WebView::set_browser_accelerator_keys_enabled`].
+    ///
+    /// Without `ICoreWebView2_16::ShowPrintUI`, [`PrintDialogKind::Browser`] calls `window.print()`
+    /// in the page.
+    pub fn show_print_ui(&self, kind: PrintDialogKind) -> Result<&Self> {
+        match kind {
+            // `window.print()` blocks the script until the dialog closes, so call it after
+            // `ExecuteScript` completes rather than waiting for it.
+            PrintDialogKind::Browser => self.eval("setTimeout(function() { window.print(); })"),
+        }
+    }
+
+    /// Call `f` when a download is about to start, to let it continue, cancel it, or save it to a
+    /// different path than the one the browser suggests. Requires `ICoreWebView2_4`.
+    pub fn on_download_starting<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(DownloadInfo) -> DownloadDecision + 'static,
+    {
+        let webview: ICoreWebView2_4 = cast_interface(self.webview.as_ref(), "ICoreWebView2_4")?;
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            webview.add_DownloadStarting(
+                callback::DownloadStartingEventHandler::create(Box::new(move |_webview, args| {
+                    if let Some(args) = args {
+                        let download = args.get_DownloadOperation()?;
+                        let mut uri = PWSTR::default();
+                        let mut mime_type = PWSTR::default();
+                        let mut result_file_path = PWSTR::default();
+                        let mut total_bytes = 0;
+                        download.get_Uri(&mut uri)?;
+                        download.get_MimeType(&mut mime_type)?;
+                        download.get_TotalBytesToReceive(&mut total_bytes)?;
+                        args.get_ResultFilePath(&mut result_file_path)?;
+
+                        let info = DownloadInfo {
+                            uri: pwstr::take_pwstr(uri),
+                            mime_type: pwstr::take_pwstr(mime_type),
+                            result_file_path: pwstr::take_pwstr(result_file_path),
+                            total_bytes: u64::try_from(total_bytes).ok(),
+                        };
+
+                        match f(info) {
+                            DownloadDecision::Allow => (),
+                            DownloadDecision::Cancel => args.put_Cancel(true)?,
+                            DownloadDecision::SaveAs(path) => args.put_ResultFilePath(path)?,
+                        }
+                    }
+                    Ok(())
+                })),
+                &mut token,
+            )?;
+        }
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_DownloadStarting(token)
+        }))
+    }
+
+    /// Decide whether pages may use the camera, microphone, geolocation, etc. instead of showing
+    /// the default WebView2 prompt. `f` receives the kind of permission and the URI of the page
+    /// which requested it. Drop the returned [`Subscription`] to show the default prompt again.
+    pub fn on_permission_requested<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(PermissionKind, &str) -> PermissionState + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_PermissionRequested(
+                callback::PermissionRequestedEventHandler::create(Box::new(
+                    move |_webview, args| {
+                        if let Some(args) = args {
+                            let mut kind = COREWEBVIEW2_PERMISSION_KIND::default();
+                            let mut uri = PWSTR::default();
+                            args.get_PermissionKind(&mut kind)?;
+                            args.get_Uri(&mut uri)?;
+                            let uri = pwstr::take_pwstr(uri);
+
+                            args.put_State(f(PermissionKind::from(kind), &uri).into())?;
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_PermissionRequested(token)
+        }))
+    }
+
+    /// Show or hide the built-in status bar which displays link targets when hovering over them,
+    /// e.g. to hide it and show the text from [`WebView::on_status_bar_text_changed`] instead.
+    pub fn set_status_bar_enabled(&self, enabled: bool) -> Result<&Self> {
+        unsafe { self.settings.put_IsStatusBarEnabled(enabled) }?;
+        Ok(self)
+    }
+
+    /// Call `f` with the text the status bar should show, e.g. to render it in the app's own
+    /// status bar together with [`WebView::set_status_bar_enabled`]. The text is empty when the
+    /// status bar should be hidden again.
+    ///
+    /// Instead of the `StatusBarTextChanged` event of `ICoreWebView2_12`, this reports the target
+    /// of the link under the pointer or with keyboard focus in every new document, through a
+    /// binding named `__webviewStatusBarTextChanged`. Text which pages set in other ways, e.g.
+    /// the old `window.status`, is not reported.
+    pub fn on_status_bar_text_changed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(String) + 'static,
+    {
+        let js = format!(
+            r#"
+            (function() {{
+                var text = '';
+                var report = function(link) {{
+                    var now = link ? link.href : '';
+                    if (now !== text) {{
+                        text = now;
+                        window.{}(now);
+                    }}
+                }};
+                var linkOf = function(node) {{
+                    return node && node.closest ? node.closest('a[href], area[href]') : null;
+                }};
+                document.addEventListener('mouseover', function(e) {{
+                    report(linkOf(e.target));
+                }}, true);
+                document.addEventListener('focusin', function(e) {{
+                    report(linkOf(e.target));
+                }}, true);
+                document.addEventListener('focusout', function() {{
+                    report(null);
+                }}, true);
+                window.addEventListener('mouseout', function(e) {{
+                    if (!e.relatedTarget) {{
+                        report(null);
+                    }}
+                }});
+            }})();"#,
+            STATUS_BAR_TEXT_BINDING
+        );
+        self.bind_event(
+            STATUS_BAR_TEXT_BINDING,
+            Box::new(move |_webview, params| {
+                if let Some(Value::String(text)) = params.into_iter().next() {
+                    f(text);
+                }
+                Ok(Value::Null)
+            }),
+            &js,
+        )
+    }
+
+    /// Allow or block scripts from using the objects added with [`WebView::add_host_object`],
+    /// which are allowed by default. This is part of the original `ICoreWebView2Settings`, so it
+    /// works with every runtime.
+    pub fn set_host_objects_allowed(&self, allowed: bool) -> Result<&Self> {
+        unsafe { self.settings.put_AreHostObjectsAllowed(allowed) }?;
+        Ok(self)
+    }
+
+    /// Enable or disable offering to fill in addresses and other form data the user entered
+    /// before, which is enabled by default. Requires `ICoreWebView2Settings4`, which was added in
+    /// WebView2 runtime 91.0.864.35.
+    pub fn set_general_autofill_enabled(&self, enabled: bool) -> Result<&Self> {
+        let settings: ICoreWebView2Settings4 =
+            cast_interface(self.settings.as_ref(), "ICoreWebView2Settings4")?;
+        unsafe { settings.put_IsGeneralAutofillEnabled(enabled) }?;
+        Ok(self)
+    }
+
+    /// Enable or disable offering to save passwords the user enters, which is disabled by
+    /// default. Requires `ICoreWebView2Settings4`, which was added in WebView2 runtime
+    /// 91.0.864.35.
+    pub fn set_password_autosave_enabled(&self, enabled: bool) -> Result<&Self> {
+        let settings: ICoreWebView2Settings4 =
+            cast_interface(self.settings.as_ref(), "ICoreWebView2Settings4")?;
+        unsafe { settings.put_IsPasswordAutosaveEnabled(enabled) }?;
+        Ok(self)
+    }
+
+    /// Enable or disable zooming the page with a pinch gesture on a touch screen or precision
+    /// touchpad, which is enabled by default. This does not affect Ctrl+wheel zooming. Requires
+    /// `ICoreWebView2Settings5`, which was added in WebView2 runtime 92.0.902.49.
+    pub fn set_pinch_zoom_enabled(&self, enabled: bool) -> Result<&Self> {
+        let settings: ICoreWebView2Settings5 =
+            cast_interface(self.settings.as_ref(), "ICoreWebView2Settings5")?;
+        unsafe { settings.put_IsPinchZoomEnabled(enabled) }?;
+        Ok(self)
+    }
+
+    /// Enable or disable going back and forward by swiping horizontally on a touch screen or
+    /// precision touchpad, which is enabled by default, e.g. because it gets in the way of
+    /// horizontal scrolling in the app.
+    ///
+    /// Without `ICoreWebView2Settings6::put_IsSwipeNavigationEnabled`, this adds an init script
+    /// setting `overscroll-behavior-x: none` on the root element of each document, which stops
+    /// overscrolling the page from turning into a swipe navigation. Scripts in the page can still
+    /// change that style back.
+    pub fn set_swipe_navigation_enabled(&self, enabled: bool) -> Result<&Self> {
+        let no_swipe_script = self.no_swipe_script.lock()?.take();
+        let (no_swipe_script, js) = match (enabled, no_swipe_script) {
+            (false, Some(id)) => (Some(id), None),
+            (false, None) => (
+                Some(self.add_script(NO_SWIPE_SCRIPT)?),
+                Some(NO_SWIPE_SCRIPT),
+            ),
+            (true, Some(id)) => {
+                self.remove_init_script(&id)?;
+                (
+                    None,
+                    Some(
+                        r#"
+                        if (document.documentElement) {
+                            document.documentElement.style.removeProperty('overscroll-behavior-x');
+                        }"#,
+                    ),
+                )
+            }
+            (true, None) => (None, None),
+        };
+        *self.no_swipe_script.lock()? = no_swipe_script;
+
+        if let Some(js) = js {
+            self.eval(js)?;
+        }
+        Ok(self)
+    }
+
+    /// Check whether the DevTools are enabled, see [`WebView::set_dev_tools_enabled`].
+    pub fn dev_tools_enabled(&self) -> Result<bool> {
+        let mut enabled = BOOL::default();
+        unsafe { self.settings.get_AreDevToolsEnabled(&mut enabled) }?;
+        Ok(enabled.as_bool())
+    }
+
+    /// Check whether the default context menus are enabled, see
+    /// [`WebView::set_default_context_menus_enabled`].
+    pub fn default_context_menus_enabled(&self) -> Result<bool> {
+        let mut enabled = BOOL::default();
+        unsafe {
+            self.settings
+                .get_AreDefaultContextMenusEnabled(&mut enabled)
+        }?;
+        Ok(enabled.as_bool())
+    }
+
+    /// Enable or disable the DevTools, e.g. behind a hidden key combination in a release build.
+    /// They start out enabled only if the `WebView` was created with `debug` set.
+    pub fn set_dev_tools_enabled(&self, enabled: bool) -> Result<&Self> {
+        unsafe { self.settings.put_AreDevToolsEnabled(enabled) }?;
+        Ok(self)
+    }
+
+    /// Enable or disable the default context menus. They start out enabled only if the
+    /// `WebView` was created with `debug` set.
+    pub fn set_default_context_menus_enabled(&self, enabled: bool) -> Result<&Self> {
+        unsafe { self.settings.put_AreDefaultContextMenusEnabled(enabled) }?;
+        Ok(self)
+    }
+
+    /// Set the color shown before the page paints, and behind any transparent parts of the page,
+    /// as `0xAARRGGBB`. Requires `ICoreWebView2Controller2`.
+    ///
+    /// Only fully opaque colors and fully transparent (alpha of `0`) are supported. A transparent
+    /// background shows whatever the parent window paints behind the `WebView`, but to really see
+    /// through to other windows or effects like acrylic, the app needs to host the `WebView` with
+    /// visual composition, which this sample does not do.
+    pub fn set_default_background_color(&self, argb: u32) -> Result<&Self> {
+        let controller: ICoreWebView2Controller2 =
+            cast_interface(&self.controller.0, "ICoreWebView2Controller2")?;
+        unsafe {
+            controller.put_DefaultBackgroundColor(COREWEBVIEW2_COLOR {
+                A: (argb >> 24) as u8,
+                R: (argb >> 16) as u8,
+                G: (argb >> 8) as u8,
+                B: argb as u8,
+            })
+        }?;
+        Ok(self)
+    }
+
+    /// Move keyboard focus into the `WebView`. [`MoveFocusReason::Next`] and
+    /// [`MoveFocusReason::Previous`] focus the first or last element in the tab order, e.g. when
+    /// the user tabs into it from a native control.
+    pub fn move_focus(&self, reason: MoveFocusReason) -> Result<&Self> {
+        unsafe { self.controller.0.MoveFocus(reason.into()) }?;
+        Ok(self)
+    }
+
+    /// Move keyboard focus to the page itself, so typing goes to the focused element instead of
+    /// the frame window. [`WebView::run`] does this after showing the frame window unless
+    /// [`WebViewBuilder::with_focus_contents_on_run`] turned it off.
+    pub fn focus_contents(&self) -> Result<&Self> {
+        self.move_focus(MoveFocusReason::Programmatic)
+    }
+
+    /// Call `f` when the `WebView` gets keyboard focus.
+    pub fn on_got_focus<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut() + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.controller.0.add_GotFocus(
+                callback::FocusChangedEventHandler::create(Box::new(move |_controller, _args| {
+                    f();
+                    Ok(())
+                })),
+                &mut token,
+            )?;
+        }
+        let controller = self.controller.clone();
+        Ok(Subscription::new(move || unsafe {
+            controller.0.remove_GotFocus(token)
+        }))
+    }
+
+    /// Call `f` when the `WebView` loses keyboard focus.
+    pub fn on_lost_focus<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut() + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.controller.0.add_LostFocus(
+                callback::FocusChangedEventHandler::create(Box::new(move |_controller, _args| {
+                    f();
+                    Ok(())
+                })),
+                &mut token,
+            )?;
+        }
+        let controller = self.controller.clone();
+        Ok(Subscription::new(move || unsafe {
+            controller.0.remove_LostFocus(token)
+        }))
+    }
+
+    /// Call `f` when the user tabs past the last (or before the first) element in the page, so
+    /// the host can move focus to the next native control. Returning `true` marks the request as
+    /// handled, otherwise focus wraps around inside the `WebView`.
+    pub fn on_move_focus_requested<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(MoveFocusReason) -> bool + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.controller.0.add_MoveFocusRequested(
+                callback::MoveFocusRequestedEventHandler::create(Box::new(
+                    move |_controller, args| {
+                        if let Some(args) = args {
+                            let mut reason = COREWEBVIEW2_MOVE_FOCUS_REASON::default();
+                            args.get_Reason(&mut reason)?;
+                            if f(MoveFocusReason::from(reason)) {
+                                args.put_Handled(true)?;
+                            }
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+        let controller = self.controller.clone();
+        Ok(Subscription::new(move || unsafe {
+            controller.0.remove_MoveFocusRequested(token)
+        }))
+    }
+
+    /// Override the scale at which the page is rendered, e.g. `1.5` for 144 DPI, and stop
+    /// following the DPI of the monitor the window is on. Requires `ICoreWebView2Controller3`.
+    ///
+    /// By default the scale follows the monitor, and owned frame windows also handle
+    /// `WM_DPICHANGED` by resizing to the suggested rectangle when they move between monitors.
+    pub fn set_rasterization_scale(&self, scale: f64) -> Result<&Self> {
+        let controller: ICoreWebView2Controller3 =
+            cast_interface(&self.controller.0, "ICoreWebView2Controller3")?;
+        unsafe {
+            controller.put_ShouldDetectMonitorScaleChanges(false)?;
+            controller.put_RasterizationScale(scale)?;
+        }
+        self.update_frame_bounds()?;
+        Ok(self)
+    }
+
+    /// Follow the DPI of the monitor the window is on again after
+    /// [`WebView::set_rasterization_scale`], or stop following it. Requires
+    /// `ICoreWebView2Controller3`.
+    pub fn set_should_detect_monitor_scale_changes(&self, detect: bool) -> Result<&Self> {
+        let controller: ICoreWebView2Controller3 =
+            cast_interface(&self.controller.0, "ICoreWebView2Controller3")?;
+        unsafe { controller.put_ShouldDetectMonitorScaleChanges(detect) }?;
+        Ok(self)
+    }
+
+    /// Call `f` with the new rasterization scale when it changes, e.g. when the window moves to a
+    /// monitor with a different DPI, to lay out native UI around the `WebView` again. Requires
+    /// `ICoreWebView2Controller3`.
+    pub fn on_rasterization_scale_changed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(f64) + 'static,
+    {
+        let controller: ICoreWebView2Controller3 =
+            cast_interface(&self.controller.0, "ICoreWebView2Controller3")?;
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            controller.add_RasterizationScaleChanged(
+                callback::RasterizationScaleChangedEventHandler::create(Box::new(
+                    move |sender, _args| {
+                        if let Some(sender) = sender {
+                            let controller: ICoreWebView2Controller3 = sender.cast()?;
+                            let mut scale = 1.0;
+                            controller.get_RasterizationScale(&mut scale)?;
+                            f(scale);
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+        Ok(Subscription::new(move || unsafe {
+            controller.remove_RasterizationScaleChanged(token)
+        }))
+    }
+
+    /// Check whether an element in the page is fullscreen, e.g. a video after the user clicked
+    /// its fullscreen button.
+    pub fn contains_fullscreen_element(&self) -> Result<bool> {
+        let mut contains = BOOL::default();
+        unsafe { self.webview.get_ContainsFullScreenElement(&mut contains) }?;
+        Ok(contains.as_bool())
+    }
+
+    /// Call `f` with [`WebView::contains_fullscreen_element`] each time an element in the page
+    /// enters or leaves fullscreen, e.g. to hide the app's own UI around the `WebView` while a
+    /// video is fullscreen.
+    pub fn on_contains_fullscreen_element_changed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(bool) + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_ContainsFullScreenElementChanged(
+                callback::ContainsFullScreenElementChangedEventHandler::create(Box::new(
+                    move |webview, _args| {
+                        if let Some(webview) = webview {
+                            let mut contains = BOOL::default();
+                            webview.get_ContainsFullScreenElement(&mut contains)?;
+                            f(contains.as_bool());
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_ContainsFullScreenElementChanged(token)
+        }))
+    }
+
+    /// Show or hide the web content without destroying it, e.g. while the app is minimized to
+    /// the tray. A hidden `WebView` stops rendering and drops its frame rate to save CPU, which
+    /// hiding the window with [`WebView::hide`] does not always do, e.g. for embedded `WebView`s
+    /// in a window which is still visible.
+    pub fn set_controller_visible(&self, visible: bool) -> Result<&Self> {
+        unsafe { self.controller.0.put_IsVisible(visible) }?;
+        Ok(self)
+    }
+
+    /// Check whether the web content is visible, see [`WebView::set_controller_visible`].
+    pub fn is_controller_visible(&self) -> Result<bool> {
+        let mut visible = BOOL::default();
+        unsafe { self.controller.0.get_IsVisible(&mut visible) }?;
+        Ok(visible.as_bool())
+    }
+
+    /// Try to suspend the WebView to save memory and CPU while the app is in the background,
+    /// returning whether the runtime agreed. The runtime refuses to suspend a visible WebView,
+    /// so this hides it first, and [`WebView::resume`] makes it visible again. Scripts and
+    /// timers are paused until then. Requires `ICoreWebView2_3`.
+    pub fn try_suspend(&self) -> Result<bool> {
+        let webview: ICoreWebView2_3 = cast_interface(self.webview.as_ref(), "ICoreWebView2_3")?;
+        unsafe { self.controller.0.put_IsVisible(false) }?;
+        callback::TrySuspendCompletedHandler::run_async(
+            self.timeout,
+            move |handler| unsafe { webview.TrySuspend(handler).map_err(Error::WindowsError) },
+            |error_code, is_successful| {
+                error_code?;
+                Ok(is_successful.as_bool())
+            },
+        )
+    }
+
+    /// Resume a WebView suspended with [`WebView::try_suspend`] and make it visible again.
+    /// Requires `ICoreWebView2_3`.
+    pub fn resume(&self) -> Result<&Self> {
+        let webview: ICoreWebView2_3 = cast_interface(self.webview.as_ref(), "ICoreWebView2_3")?;
+        unsafe {
+            webview.Resume()?;
+            self.controller.0.put_IsVisible(true)?;
+        }
+        Ok(self)
+    }
+
+    pub fn get_window(&self) -> HWND {
+        *self.parent
+    }
+
+    pub fn navigate(&self, url: &str) -> Result<&Self> {
         let url = url.into();
-        *self.url.lock().expect("lock url") = url;
+        *self.url.lock()? = url;
+        Ok(self)
+    }
+
+    /// Navigate to `url` immediately and process messages until the navigation completes,
+    /// returning the `COREWEBVIEW2_WEB_ERROR_STATUS` code, which is `0` if it succeeded. This
+    /// does not show the frame window, so it also works for scripted flows before
+    /// [`WebView::run`].
+    pub fn navigate_and_wait(&self, url: &str) -> Result<i32> {
+        let webview = self.webview.as_ref();
+        let (tx, rx) = mpsc::channel();
+        let handler =
+            callback::NavigationCompletedEventHandler::create(Box::new(move |_sender, args| {
+                let mut web_error_status = COREWEBVIEW2_WEB_ERROR_STATUS::default();
+                if let Some(args) = args {
+                    args.get_WebErrorStatus(&mut web_error_status)?;
+                }
+                logger::log(format_args!("navigation completed: {}", web_error_status.0));
+                tx.send(web_error_status.0).expect("send over mpsc channel");
+                Ok(())
+            }));
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            webview.add_NavigationCompleted(handler, &mut token)?;
+            logger::log(format_args!("navigation started: {}", url));
+            let result = webview
+                .Navigate(url)
+                .map_err(Error::WindowsError)
+                .and_then(|_| wait_with_pump(rx, self.timeout));
+            webview.remove_NavigationCompleted(token)?;
+            result
+        }
+    }
+
+    /// Show `html` as the document, without serving it from anywhere, e.g. for generated
+    /// reports. Like [`WebView::navigate_with_request`], this does not wait for [`WebView::run`].
+    ///
+    /// Content up to the 2 MB limit of `NavigateToString` is loaded directly, and runs in an
+    /// opaque origin as if it were `about:blank`. Anything bigger is written to `index.html` in a
+    /// new temporary folder, which is mapped to `https://load-html.example/` with
+    /// `ICoreWebView2_3::SetVirtualHostNameToFolderMapping`, so the page runs in that origin
+    /// instead and can load other resources relative to it. The temporary folder is deleted by
+    /// the next call to `load_html`, or when the last clone of the `WebView` is dropped.
+    pub fn load_html(&self, html: &str) -> Result<&Self> {
+        let mut loaded_html = self.loaded_html.lock()?;
+        *loaded_html = None;
+
+        if html.len() <= NAVIGATE_TO_STRING_LIMIT {
+            unsafe { self.webview.NavigateToString(html) }?;
+            return Ok(self);
+        }
+
+        let webview: ICoreWebView2_3 = cast_interface(self.webview.as_ref(), "ICoreWebView2_3")?;
+        let temp = TempHtml::new(html)?;
+        unsafe {
+            let _ = webview.ClearVirtualHostNameToFolderMapping(LOAD_HTML_HOST);
+            webview.SetVirtualHostNameToFolderMapping(
+                LOAD_HTML_HOST,
+                temp.0.to_string_lossy().as_ref(),
+                COREWEBVIEW2_HOST_RESOURCE_ACCESS_KIND_DENY_CORS,
+            )?;
+            webview.Navigate(format!("https://{}/index.html", LOAD_HTML_HOST))?;
+        }
+        *loaded_html = Some(temp);
+        Ok(self)
+    }
+
+    /// Navigate immediately with a custom HTTP method, headers and body, e.g. to send an
+    /// `Authorization` header or `POST` form data. Unlike [`WebView::navigate`], this does not
+    /// wait for [`WebView::run`]. Requires `ICoreWebView2_2` and `ICoreWebView2Environment2`.
+    pub fn navigate_with_request(&self, request: NavigationRequest) -> Result<&Self> {
+        let webview: ICoreWebView2_2 = cast_interface(self.webview.as_ref(), "ICoreWebView2_2")?;
+        let request = self.environment().create_web_resource_request(&request)?;
+        unsafe { webview.NavigateWithWebResourceRequest(request) }?;
+        Ok(self)
+    }
+
+    /// Get the WebView2 environment this `WebView` was created in, e.g. to create requests and
+    /// responses for other WebView2 APIs.
+    pub fn environment(&self) -> Environment {
+        Environment(self.environment.clone())
+    }
+
+    /// Reload the current page, bypassing the HTTP cache like Ctrl+F5 does, e.g. while developing
+    /// the page.
+    ///
+    /// This calls the DevTools Protocol `Page.reload` method with `ignoreCache`, so unlike
+    /// clearing the disk cache it does not affect other pages. Subresources which the page loads
+    /// later, e.g. with `fetch`, can still come from the cache, and a service worker can still
+    /// answer the requests.
+    pub fn hard_reload(&self) -> Result<&Self> {
+        self.call_devtools_protocol_method("Page.reload", r#"{"ignoreCache":true}"#)?;
+        Ok(self)
+    }
+
+    /// List the back/forward history, oldest first, e.g. for a history menu in an embedded
+    /// browser. This calls the DevTools Protocol `Page.getNavigationHistory` method through
+    /// [`WebView::call_devtools_protocol_method`].
+    pub fn history_entries(&self) -> Result<Vec<HistoryEntry>> {
+        let history: NavigationHistory = serde_json::from_value(
+            self.call_devtools_protocol_method("Page.getNavigationHistory", "{}")?,
+        )?;
+        let mut entries = history.entries;
+        if let Some(entry) = entries.get_mut(history.current_index) {
+            entry.is_current = true;
+        }
+        Ok(entries)
+    }
+
+    /// Go back or forward to the [`HistoryEntry`] with `id`, from [`WebView::history_entries`].
+    /// This calls the DevTools Protocol `Page.navigateToHistoryEntry` method.
+    pub fn navigate_to_history_entry(&self, id: i64) -> Result<&Self> {
+        let params = serde_json::json!({ "entryId": id });
+        self.call_devtools_protocol_method("Page.navigateToHistoryEntry", &params.to_string())?;
+        Ok(self)
+    }
+
+    /// Forget the back/forward history, keeping only the current document, e.g. after the
+    /// redirects of a sign in flow. This calls the DevTools Protocol
+    /// `Page.resetNavigationHistory` method through [`WebView::call_devtools_protocol_method`].
+    pub fn clear_history(&self) -> Result<&Self> {
+        self.call_devtools_protocol_method("Page.resetNavigationHistory", "{}")?;
+        Ok(self)
+    }
+
+    /// Get the process ID of the browser process which hosts this WebView.
+    pub fn browser_process_id(&self) -> Result<u32> {
+        let mut process_id = 0;
+        unsafe { self.webview.get_BrowserProcessId(&mut process_id) }?;
+        Ok(process_id)
+    }
+
+    /// List the processes which belong to this WebView's environment, e.g. to show their memory
+    /// use in a task manager.
+    ///
+    /// This needs `ICoreWebView2Environment8::GetProcessInfos`, so it always fails with
+    /// [`Error::UnsupportedInterface`] with these bindings. The DevTools Protocol only lists the
+    /// processes to the browser target, not to the page, and [`WebView::browser_process_id`]
+    /// still gets the browser process.
+    pub fn process_infos(&self) -> Result<Vec<ProcessInfo>> {
+        Err(Error::UnsupportedInterface("ICoreWebView2Environment8"))
+    }
+
+    /// Get the URI of the current top level document.
+    pub fn source(&self) -> Result<String> {
+        let mut source = PWSTR::default();
+        unsafe { self.webview.get_Source(&mut source) }?;
+        Ok(pwstr::take_pwstr(source))
+    }
+
+    /// Call `f` with the new URI whenever [`WebView::source`] changes, e.g. to keep an address
+    /// bar in sync. This includes same document navigations such as `history.pushState` in
+    /// single page apps, in which case the second argument (`is_new_document`) is `false`.
+    pub fn on_source_changed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(String, bool) + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_SourceChanged(
+                callback::SourceChangedEventHandler::create(Box::new(move |webview, args| {
+                    if let (Some(webview), Some(args)) = (webview, args) {
+                        let mut source = PWSTR::default();
+                        let mut is_new_document = BOOL::default();
+                        webview.get_Source(&mut source)?;
+                        args.get_IsNewDocument(&mut is_new_document)?;
+
+                        f(pwstr::take_pwstr(source), is_new_document.as_bool());
+                    }
+                    Ok(())
+                })),
+                &mut token,
+            )?;
+        }
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_SourceChanged(token)
+        }))
+    }
+
+    /// Call `f` when the new document starts loading, with `true` if it is an error page, e.g. to
+    /// show a spinner.
+    ///
+    /// The events for each navigation are raised in this order: `NavigationStarting`,
+    /// `SourceChanged` (see [`WebView::on_source_changed`]), `ContentLoading`, `HistoryChanged`
+    /// and finally `NavigationCompleted` (see [`WebView::on_navigation_completed`]).
+    pub fn on_content_loading<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(bool) + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_ContentLoading(
+                callback::ContentLoadingEventHandler::create(Box::new(move |_webview, args| {
+                    if let Some(args) = args {
+                        let mut is_error_page = BOOL::default();
+                        args.get_IsErrorPage(&mut is_error_page)?;
+                        f(is_error_page.as_bool());
+                    }
+                    Ok(())
+                })),
+                &mut token,
+            )?;
+        }
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_ContentLoading(token)
+        }))
+    }
+
+    /// Call `f` when a navigation finishes, with `true` if it succeeded and the
+    /// `COREWEBVIEW2_WEB_ERROR_STATUS` code otherwise, e.g. to hide a spinner. See
+    /// [`WebView::on_content_loading`] for the order of the navigation events.
+    ///
+    /// This is independent of the navigation [`WebView::run`] waits for before showing the
+    /// window, which uses its own handler.
+    pub fn on_navigation_completed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(bool, i32) + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_NavigationCompleted(
+                callback::NavigationCompletedEventHandler::create(Box::new(
+                    move |_webview, args| {
+                        if let Some(args) = args {
+                            let mut is_success = BOOL::default();
+                            let mut web_error_status = COREWEBVIEW2_WEB_ERROR_STATUS::default();
+                            args.get_IsSuccess(&mut is_success)?;
+                            args.get_WebErrorStatus(&mut web_error_status)?;
+                            f(is_success.as_bool(), web_error_status.0);
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_NavigationCompleted(token)
+        }))
+    }
+
+    /// Call `f` once, when the next navigation completes, and then remove the handler, e.g. to
+    /// hide a splash screen when the first page is showing. This includes documents loaded with
+    /// [`WebView::load_html`], and pages which failed to load, which still show an error page.
+    ///
+    /// Register this before the first navigation starts, e.g. before [`WebView::run`], otherwise
+    /// it waits for the one after that. The handler only keeps a [`WeakWebView`], so `f` is
+    /// dropped without being called if the `WebView` goes away first. Dropping the returned
+    /// [`Subscription`] before then removes the handler without calling `f`.
+    pub fn on_ready<F>(&self, f: F) -> Result<Subscription>
+    where
+        F: FnOnce(&WebView) + 'static,
+    {
+        let weak = self.downgrade();
+        let registration = Arc::new(Mutex::new(None));
+        let handler_registration = registration.clone();
+        let mut f = Some(f);
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_NavigationCompleted(
+                callback::NavigationCompletedEventHandler::create(Box::new(
+                    move |_webview, _args| {
+                        if let Some(webview) = weak.upgrade() {
+                            if let Ok(mut registration) = handler_registration.lock() {
+                                if let Some(token) = registration.take() {
+                                    webview.webview.remove_NavigationCompleted(token)?;
+                                }
+                            }
+                            if let Some(f) = f.take() {
+                                f(&webview);
+                            }
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+        *registration.lock()? = Some(token);
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || {
+            // The handler takes the token when it runs, so only remove it if it has not.
+            let token = match registration.lock() {
+                Ok(mut registration) => registration.take(),
+                Err(_) => None,
+            };
+            match token {
+                Some(token) => unsafe { webview.remove_NavigationCompleted(token) },
+                None => Ok(()),
+            }
+        }))
+    }
+
+    /// Call `f` with the status and headers of every response the `WebView` receives, e.g. for
+    /// logging. The response cannot be modified, but [`ResponseInfo::get_content`] can fetch
+    /// the body. Requires `ICoreWebView2_2`.
+    pub fn on_web_resource_response_received<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(ResponseInfo) + 'static,
+    {
+        let webview: ICoreWebView2_2 = cast_interface(self.webview.as_ref(), "ICoreWebView2_2")?;
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            webview.add_WebResourceResponseReceived(
+                callback::WebResourceResponseReceivedEventHandler::create(Box::new(
+                    move |_webview, args| {
+                        if let Some(args) = args {
+                            let request = args.get_Request()?;
+                            let response = args.get_Response()?;
+                            let mut uri = PWSTR::default();
+                            let mut method = PWSTR::default();
+                            let mut status_code = 0;
+                            let mut reason_phrase = PWSTR::default();
+                            request.get_Uri(&mut uri)?;
+                            request.get_Method(&mut method)?;
+                            response.get_StatusCode(&mut status_code)?;
+                            response.get_ReasonPhrase(&mut reason_phrase)?;
+                            let headers = collect_headers(response.get_Headers()?.GetIterator()?)?;
+
+                            f(ResponseInfo {
+                                uri: pwstr::take_pwstr(uri),
+                                method: pwstr::take_pwstr(method),
+                                status_code,
+                                reason_phrase: pwstr::take_pwstr(reason_phrase),
+                                headers,
+                                response,
+                            });
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_WebResourceResponseReceived(token)
+        }))
+    }
+
+    /// Answer every request for `https://<host>/...` with the [`ResourceResponse`] `f` returns
+    /// for its URI, e.g. to serve pages and assets which are embedded in the app. `host` may
+    /// contain lowercase ASCII letters, digits, `-` and `.`, otherwise this returns
+    /// [`Error::InvalidHostName`].
+    ///
+    /// Like [`WebView::load_html`], this serves a virtual host rather than a custom scheme, which
+    /// would have to be registered with `ICoreWebView2EnvironmentOptions4` before the environment
+    /// is created. Requests for the host never reach the network, so pick a name which can never
+    /// be a real site, e.g. under the reserved `.example` domain. Since it is an ordinary `https`
+    /// origin, the pages can also use features which need a secure context.
+    pub fn register_virtual_host_handler<F>(&self, host: &str, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(&str) -> ResourceResponse + 'static,
+    {
+        if host.is_empty()
+            || !host
+                .bytes()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-' || c == b'.')
+        {
+            return Err(Error::InvalidHostName(String::from(host)));
+        }
+
+        let prefix = format!("https://{}/", host);
+        let filter = format!("{}*", prefix);
+        unsafe {
+            self.webview.AddWebResourceRequestedFilter(
+                filter.as_str(),
+                COREWEBVIEW2_WEB_RESOURCE_CONTEXT_ALL,
+            )
+        }?;
+
+        let environment = self.environment();
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_WebResourceRequested(
+                callback::WebResourceRequestedEventHandler::create(Box::new(
+                    move |_webview, args| {
+                        if let Some(args) = args {
+                            let mut uri = PWSTR::default();
+                            args.get_Request()?.get_Uri(&mut uri)?;
+                            let uri = pwstr::take_pwstr(uri);
+                            if !uri.starts_with(&prefix) {
+                                return Ok(());
+                            }
+
+                            let response = f(&uri);
+                            match environment.create_web_resource_response(
+                                Some(&response.content),
+                                response.status_code,
+                                &response.reason_phrase,
+                                &response.headers,
+                            ) {
+                                Ok(response) => args.put_Response(response)?,
+                                Err(err) => logger::log(format_args!(
+                                    "failed to create the response for {}: {:?}",
+                                    uri, err
+                                )),
+                            }
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_WebResourceRequested(token)?;
+            webview.RemoveWebResourceRequestedFilter(filter, COREWEBVIEW2_WEB_RESOURCE_CONTEXT_ALL)
+        }))
+    }
+
+    /// Call `f` whenever an `<iframe>` is added to the page, with a [`FrameInfo`] which can
+    /// watch for it being removed again. The main frame of the page never raises this event.
+    /// Requires `ICoreWebView2_4`.
+    pub fn on_frame_created<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(FrameInfo) + 'static,
+    {
+        let webview: ICoreWebView2_4 = cast_interface(self.webview.as_ref(), "ICoreWebView2_4")?;
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            webview.add_FrameCreated(
+                callback::FrameCreatedEventHandler::create(Box::new(move |_webview, args| {
+                    if let Some(args) = args {
+                        let frame = args.get_Frame()?;
+                        let mut name = PWSTR::default();
+                        frame.get_Name(&mut name)?;
+                        f(FrameInfo {
+                            name: pwstr::take_pwstr(name),
+                            frame,
+                        });
+                    }
+                    Ok(())
+                })),
+                &mut token,
+            )?;
+        }
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_FrameCreated(token)
+        }))
+    }
+
+    /// Call `f` with the URI whenever an `<iframe>` starts to navigate. Return `false` to
+    /// cancel the navigation. Navigations of the page itself do not raise this event.
+    pub fn on_frame_navigation_starting<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(String) -> bool + 'static,
+    {
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_FrameNavigationStarting(
+                callback::NavigationStartingEventHandler::create(Box::new(
+                    move |_webview, args| {
+                        if let Some(args) = args {
+                            let mut uri = PWSTR::default();
+                            args.get_Uri(&mut uri)?;
+                            if !f(pwstr::take_pwstr(uri)) {
+                                args.put_Cancel(true)?;
+                            }
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_FrameNavigationStarting(token)
+        }))
+    }
+
+    /// Call `f` with the paths of all the files dropped onto the `WebView` from Explorer, instead
+    /// of navigating to them. Drag and drop within the page works as before, but while the
+    /// returned [`Subscription`] is alive the page no longer receives files dropped from outside.
+    ///
+    /// The WebView2 child window covers the frame and registers its own drop target, and there is
+    /// no `AllowExternalDrop` setting to turn it off. So when the page sees a `dragenter` which
+    /// carries files, this shows a nearly transparent overlay over the `WebView` with an
+    /// `IDropTarget` registered by `RegisterDragDrop`, which takes the rest of the drag. That needs
+    /// OLE, so this must be called on the thread which created the `WebView`, in an STA apartment.
+    pub fn on_files_dropped<F>(&self, f: F) -> Result<Subscription>
+    where
+        F: FnMut(Vec<PathBuf>) + 'static,
+    {
+        unsafe { Ole::OleInitialize(ptr::null_mut()) }?;
+        let overlay = drop_target::create_overlay(*self.parent);
+        let target = drop_target::FileDropTarget::create(overlay, Box::new(f));
+        if let Err(error) = unsafe { Ole::RegisterDragDrop(overlay, target) } {
+            unsafe {
+                WindowsAndMessaging::DestroyWindow(overlay);
+                Ole::OleUninitialize();
+            }
+            return Err(Error::WindowsError(error));
+        }
+
+        let js = format!(
+            r#"
+            (function() {{
+                window.addEventListener('dragenter', function(e) {{
+                    var types = e.dataTransfer ? Array.prototype.slice.call(e.dataTransfer.types) : [];
+                    if (types.indexOf('Files') !== -1) {{
+                        window.{}();
+                    }}
+                }}, true);
+            }})();"#,
+            FILES_DRAGGED_BINDING
+        );
+        let binding = self.bind_event(
+            FILES_DRAGGED_BINDING,
+            Box::new(move |webview, _params| {
+                let mut bounds = RECT::default();
+                unsafe { webview.controller.0.get_Bounds(&mut bounds) }?;
+                let mut origin = POINT {
+                    x: bounds.left,
+                    y: bounds.top,
+                };
+                unsafe { Gdi::ClientToScreen(*webview.parent, &mut origin) };
+                drop_target::show_overlay(
+                    overlay,
+                    origin.x,
+                    origin.y,
+                    bounds.right - bounds.left,
+                    bounds.bottom - bounds.top,
+                );
+                Ok(Value::Null)
+            }),
+            &js,
+        );
+        let binding = match binding {
+            Ok(binding) => binding,
+            Err(error) => {
+                unsafe {
+                    let _ = Ole::RevokeDragDrop(overlay);
+                    WindowsAndMessaging::DestroyWindow(overlay);
+                    Ole::OleUninitialize();
+                }
+                return Err(error);
+            }
+        };
+
+        Ok(Subscription::new(move || {
+            drop(binding);
+            unsafe {
+                let result = Ole::RevokeDragDrop(overlay);
+                WindowsAndMessaging::DestroyWindow(overlay);
+                Ole::OleUninitialize();
+                result
+            }
+        }))
+    }
+
+    /// Add a script which runs at the start of every new document, before any of the page's
+    /// own scripts. See [`WebView::add_init_script`] for the order scripts run in.
+    pub fn init(&self, js: &str) -> Result<&Self> {
+        self.add_init_script(js)?;
+        Ok(self)
+    }
+
+    /// Same as [`WebView::init`], but return the ID of the script so it can be removed later
+    /// with [`WebView::remove_init_script`].
+    ///
+    /// Each call waits for the script to be registered before returning, so scripts run in the
+    /// order they were added. The `window.external.invoke` shim used by [`WebView::bind`] is
+    /// added by [`WebView::create`], so it is always in place before any script added here.
+    pub fn add_init_script(&self, js: &str) -> Result<String> {
+        let id = self.add_script(js)?;
+        self.init_scripts
+            .lock()?
+            .push((id.clone(), String::from(js)));
+        Ok(id)
+    }
+
+    /// Add a script like [`WebView::add_init_script`], but leave it out of the scripts which
+    /// [`WebView::recover`] adds again, e.g. because creating the `WebView` adds it anyway.
+    fn add_script(&self, js: &str) -> Result<String> {
+        let webview = self.webview.clone();
+        let js = String::from(js);
+        callback::AddScriptToExecuteOnDocumentCreatedCompletedHandler::run_async(
+            self.timeout,
+            move |handler| unsafe {
+                webview
+                    .AddScriptToExecuteOnDocumentCreated(js, handler)
+                    .map_err(Error::WindowsError)
+            },
+            |error_code, id| {
+                error_code?;
+                Ok(id)
+            },
+        )
+    }
+
+    /// Stop running a script added with [`WebView::add_init_script`] in new documents. Documents
+    /// which have already loaded are not affected.
+    pub fn remove_init_script(&self, id: &str) -> Result<&Self> {
+        unsafe { self.webview.RemoveScriptToExecuteOnDocumentCreated(id) }?;
+        self.init_scripts
+            .lock()?
+            .retain(|(script_id, _)| script_id != id);
+        Ok(self)
+    }
+
+    /// Expose `object` to scripts in this `WebView` as `chrome.webview.hostObjects.<name>`, which
+    /// returns a promise for each property or method call, or as
+    /// `chrome.webview.hostObjects.sync.<name>`, which blocks the script until the call returns
+    /// instead. Frames are not included.
+    ///
+    /// `object` must implement `IDispatch`, and should provide type information through
+    /// `GetTypeInfo` so scripts can enumerate its members. Calls from scripts arrive on the UI
+    /// thread which created this `WebView`, through its message loop, so the object must live in
+    /// that single threaded apartment. While a synchronous call is waiting, the page cannot
+    /// respond, so keep those calls short and never block on the page from inside them.
+    pub fn add_host_object(&self, name: &str, object: IDispatch) -> Result<&Self> {
+        // `AddHostObjectToScript` takes a `VARIANT`, which is `VT_DISPATCH` followed by the
+        // `IDispatch` pointer after 3 reserved words. It adds its own reference to the object.
+        #[repr(C)]
+        struct DispatchVariant {
+            vt: u16,
+            reserved: [u16; 3],
+            dispatch: *mut std::ffi::c_void,
+            record_info: *mut std::ffi::c_void,
+        }
+        const VT_DISPATCH: u16 = 9;
+
+        let mut variant = DispatchVariant {
+            vt: VT_DISPATCH,
+            reserved: [0; 3],
+            dispatch: unsafe { mem::transmute_copy(&object) },
+            record_info: ptr::null_mut(),
+        };
+        unsafe {
+            self.webview
+                .AddHostObjectToScript(name, &mut variant as *mut DispatchVariant as *mut _)
+        }?;
+        Ok(self)
+    }
+
+    /// Stop exposing the object added with [`WebView::add_host_object`] as `name`. Scripts which
+    /// already have a reference to it keep working until they drop it.
+    pub fn remove_host_object(&self, name: &str) -> Result<&Self> {
+        unsafe { self.webview.RemoveHostObjectFromScript(name) }?;
+        Ok(self)
+    }
+
+    pub fn eval(&self, js: &str) -> Result<&Self> {
+        self.execute_script(js)?;
         Ok(self)
     }
 
-    pub fn init(&self, js: &str) -> Result<&Self> {
+    /// Evaluate `js` and deserialize its completion value into `T`. The script may be a single
+    /// expression or a list of statements, in which case the value of the last one is used.
+    ///
+    /// A script which throws returns [`Error::ScriptError`] with the stringified exception,
+    /// while a result which does not match `T` returns [`Error::JsonError`].
+    pub fn eval_json<T: DeserializeOwned>(&self, js: &str) -> Result<T> {
+        // `ExecuteScript` reports a thrown exception as `null`, so wrap the script to tell them
+        // apart from a script which really evaluates to `null`.
+        let js = format!(
+            r#"
+            (function() {{
+                try {{
+                    return {{ result: window.eval({}) }};
+                }} catch (e) {{
+                    return {{ error: String(e) }};
+                }}
+            }})()"#,
+            serde_json::to_string(js)?
+        );
+
+        let result: EvalResult = serde_json::from_str(&self.execute_script(&js)?)?;
+        match result.error {
+            Some(error) => Err(Error::ScriptError(error)),
+            None => Ok(serde_json::from_value(
+                result.result.unwrap_or(Value::Null),
+            )?),
+        }
+    }
+
+    /// Evaluate the boolean expression `js` every `poll` interval, processing messages in
+    /// between, until it is `true`, e.g. `document.querySelector('#results') !== null` to wait
+    /// for an element when test-driving a page. Returns [`Error::Timeout`] if it is still `false`
+    /// after `timeout`, and [`Error::ScriptError`] as soon as it throws.
+    pub fn wait_for_condition(&self, js: &str, timeout: Duration, poll: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.eval_json::<bool>(js)? {
+                return Ok(());
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_millis(0) {
+                logger::log(format_args!(
+                    "condition still false after {:?}: {}",
+                    timeout, js
+                ));
+                return Err(Error::Timeout);
+            }
+            pump_for(poll.min(remaining))?;
+        }
+    }
+
+    /// Evaluate `js` in the `<iframe>` named `frame_name`, e.g. `<iframe name="editor">`, and
+    /// return its completion value. Returns [`Error::FrameNotFound`] if there is no such frame,
+    /// and [`Error::ScriptError`] if the script throws.
+    ///
+    /// Without `ICoreWebView2Frame2::ExecuteScript`, this finds the frame with `Page.getFrameTree`
+    /// from the DevTools Protocol and evaluates the script in a new isolated world for it. The
+    /// script can use the DOM of the frame, but not the globals its own scripts define. Frames
+    /// which run in another process, e.g. some cross-origin frames, are not found.
+    pub fn eval_in_frame(&self, frame_name: &str, js: &str) -> Result<Value> {
+        let frame_tree = self.call_devtools_protocol_method("Page.getFrameTree", "{}")?;
+        let frame_id = find_frame_id(&frame_tree["frameTree"], frame_name)
+            .ok_or_else(|| Error::FrameNotFound(String::from(frame_name)))?;
+
+        let params = serde_json::json!({
+            "frameId": frame_id,
+            "worldName": "eval_in_frame",
+        });
+        let world =
+            self.call_devtools_protocol_method("Page.createIsolatedWorld", &params.to_string())?;
+
+        let params = serde_json::json!({
+            "expression": js,
+            "contextId": world["executionContextId"],
+            "returnByValue": true,
+            "awaitPromise": true,
+        });
+        self.evaluate(&params)
+    }
+
+    /// Call the DevTools Protocol `Runtime.evaluate` method with `params` and return the value of
+    /// the result, or [`Error::ScriptError`] if the script throws.
+    fn evaluate(&self, params: &Value) -> Result<Value> {
+        let mut result =
+            self.call_devtools_protocol_method("Runtime.evaluate", &params.to_string())?;
+        if let Some(exception) = result.get("exceptionDetails") {
+            let description = exception["exception"]["description"]
+                .as_str()
+                .or_else(|| exception["text"].as_str())
+                .unwrap_or_default();
+            return Err(Error::ScriptError(String::from(description)));
+        }
+        Ok(result["result"]["value"].take())
+    }
+
+    fn execute_script(&self, js: &str) -> Result<String> {
         let webview = self.webview.clone();
         let js = String::from(js);
-        callback::AddScriptToExecuteOnDocumentCreatedCompletedHandler::wait_for_async_operation(
-            Box::new(move |handler| unsafe {
+
+        callback::ExecuteScriptCompletedHandler::run_async(
+            self.timeout,
+            move |handler| unsafe {
                 webview
-                    .AddScriptToExecuteOnDocumentCreated(js, handler)
+                    .ExecuteScript(js, handler)
                     .map_err(Error::WindowsError)
+            },
+            |error_code, result| {
+                error_code?;
+                Ok(result)
+            },
+        )
+    }
+
+    /// Capture the part of the page inside `rect` as an image in `format`, e.g. for a thumbnail.
+    /// Returns [`Error::EmptyCaptureRegion`] if `rect` has no area.
+    ///
+    /// This calls the DevTools Protocol `Page.captureScreenshot` method with a clip, so `rect` is
+    /// in CSS pixels relative to the top left of the document, not client coordinates, and may
+    /// extend past the part which is scrolled into view. The image is scaled by the device
+    /// pixel ratio, so at 150% it is 1.5 times the size of `rect`.
+    pub fn capture_region(&self, rect: RECT, format: ImageFormat) -> Result<Vec<u8>> {
+        if rect.right <= rect.left || rect.bottom <= rect.top {
+            return Err(Error::EmptyCaptureRegion);
+        }
+
+        let params = serde_json::json!({
+            "format": format.devtools_value(),
+            "clip": {
+                "x": rect.left,
+                "y": rect.top,
+                "width": rect.right - rect.left,
+                "height": rect.bottom - rect.top,
+                "scale": 1,
+            },
+            "captureBeyondViewport": true,
+        });
+        let result =
+            self.call_devtools_protocol_method("Page.captureScreenshot", &params.to_string())?;
+        match result.get("data") {
+            Some(Value::String(data)) => decode_base64(data).ok_or_else(|| {
+                Error::DevToolsProtocolError(String::from("invalid screenshot data"))
             }),
-            Box::new(|error_code, _id| error_code),
-        )?;
-        Ok(self)
+            _ => Err(Error::DevToolsProtocolError(String::from(
+                "missing screenshot data",
+            ))),
+        }
     }
 
-    pub fn eval(&self, js: &str) -> Result<&Self> {
+    /// Call a [Chrome DevTools Protocol](https://chromedevtools.github.io/devtools-protocol/)
+    /// method, e.g. `Page.navigate`, with its parameters serialized as a JSON object and return
+    /// the result object. A protocol error returns [`Error::DevToolsProtocolError`] with the
+    /// message reported by the browser.
+    pub fn call_devtools_protocol_method(&self, method: &str, params_json: &str) -> Result<Value> {
         let webview = self.webview.clone();
-        let js = String::from(js);
-        callback::ExecuteScriptCompletedHandler::wait_for_async_operation(
-            Box::new(move |handler| unsafe {
+        let method = String::from(method);
+        let params_json = String::from(params_json);
+
+        let (error_code, result) = callback::CallDevToolsProtocolMethodCompletedHandler::run_async(
+            self.timeout,
+            move |handler| unsafe {
                 webview
-                    .ExecuteScript(js, handler)
+                    .CallDevToolsProtocolMethod(method, params_json, handler)
                     .map_err(Error::WindowsError)
-            }),
-            Box::new(|error_code, _result| error_code),
+            },
+            |error_code, result| Ok((error_code, result)),
+        )?;
+        match error_code {
+            Ok(()) => Ok(serde_json::from_str(&result)?),
+            Err(err) => match serde_json::from_str::<Value>(&result) {
+                Ok(Value::Object(error)) => {
+                    Err(Error::DevToolsProtocolError(match error.get("message") {
+                        Some(Value::String(message)) => message.clone(),
+                        _ => result,
+                    }))
+                }
+                _ => Err(Error::WindowsError(err)),
+            },
+        }
+    }
+
+    /// Subscribe to a DevTools Protocol event, e.g. `Network.responseReceived`, and call `f` with
+    /// its parameters each time it is raised. Most events also need their domain to be enabled
+    /// first with [`WebView::call_devtools_protocol_method`], e.g. `Network.enable`.
+    pub fn on_devtools_protocol_event<F>(&self, event: &str, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(Value) + 'static,
+    {
+        let receiver = unsafe { self.webview.GetDevToolsProtocolEventReceiver(event)? };
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            receiver.add_DevToolsProtocolEventReceived(
+                callback::DevToolsProtocolEventReceivedEventHandler::create(Box::new(
+                    move |_webview, args| {
+                        if let Some(args) = args {
+                            let mut params = PWSTR::default();
+                            if args.get_ParameterObjectAsJson(&mut params).is_ok() {
+                                let params = pwstr::take_pwstr(params);
+                                if let Ok(params) = serde_json::from_str(&params) {
+                                    f(params);
+                                }
+                            }
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+
+        Ok(Subscription::new(move || unsafe {
+            receiver.remove_DevToolsProtocolEventReceived(token)
+        }))
+    }
+
+    /// Set the `prefers-color-scheme` media feature the page sees, e.g. to follow the app's dark
+    /// theme. [`PreferredColorScheme::Auto`] goes back to following the Windows setting.
+    ///
+    /// Without `ICoreWebView2Profile::put_PreferredColorScheme`, this uses
+    /// `Emulation.setEmulatedMedia` from the DevTools Protocol, which only affects this `WebView`
+    /// rather than the whole profile, and does not change the color of built-in UI such as
+    /// scrollbars or dialogs.
+    pub fn set_preferred_color_scheme(&self, scheme: PreferredColorScheme) -> Result<&Self> {
+        let params = serde_json::json!({
+            "features": [{
+                "name": "prefers-color-scheme",
+                "value": scheme.media_value(),
+            }],
+        });
+        self.call_devtools_protocol_method("Emulation.setEmulatedMedia", &params.to_string())?;
+        Ok(self)
+    }
+
+    /// Store `cookie` in the profile with `ICoreWebView2CookieManager` and return once the
+    /// browser has finished, so the next request already sends it.
+    pub fn set_cookie(&self, cookie: &Cookie) -> Result<&Self> {
+        let webview: ICoreWebView2_2 = cast_interface(self.webview.as_ref(), "ICoreWebView2_2")?;
+        let cookie_manager = unsafe { webview.get_CookieManager() }?;
+        unsafe {
+            let new_cookie = cookie_manager.CreateCookie(
+                cookie.name.as_str(),
+                cookie.value.as_str(),
+                cookie.domain.as_str(),
+                cookie.path.as_str(),
+            )?;
+            if let Some(expires) = cookie.expires {
+                let expires = expires
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                new_cookie.put_Expires(expires)?;
+            }
+            new_cookie.put_IsHttpOnly(cookie.http_only)?;
+            new_cookie.put_IsSecure(cookie.secure)?;
+            cookie_manager.AddOrUpdateCookie(new_cookie)?;
+        }
+
+        // `AddOrUpdateCookie` does not report when it is done, but the cookie manager handles
+        // calls in order, so once a `GetCookies` call which follows it completes, so has the add.
+        let uri = format!(
+            "{}://{}{}",
+            if cookie.secure { "https" } else { "http" },
+            cookie.domain.trim_start_matches('.'),
+            cookie.path
+        );
+        callback::GetCookiesCompletedHandler::run_async(
+            self.timeout,
+            move |handler| unsafe {
+                cookie_manager
+                    .GetCookies(uri, handler)
+                    .map_err(Error::WindowsError)
+            },
+            |error_code, _cookies| {
+                error_code?;
+                Ok(())
+            },
         )?;
         Ok(self)
     }
 
-    pub fn dispatch<F>(&self, f: F) -> Result<&Self>
+    /// Clear cookies, the HTTP cache and site storage, e.g. for a "sign out" feature, and return
+    /// once the browser has finished.
+    ///
+    /// This uses the DevTools Protocol in place of `ICoreWebView2Profile2::ClearBrowsingData`.
+    /// Cookies and the HTTP cache are cleared for every site, but the storage kinds can only be
+    /// cleared for the origin of the current document.
+    pub fn clear_browsing_data(&self, kinds: BrowsingDataKinds) -> Result<()> {
+        if kinds.contains(BrowsingDataKinds::COOKIES) {
+            self.call_devtools_protocol_method("Network.clearBrowserCookies", "{}")?;
+        }
+
+        if kinds.contains(BrowsingDataKinds::DISK_CACHE) {
+            self.call_devtools_protocol_method("Network.clearBrowserCache", "{}")?;
+        }
+
+        let storage_types = kinds.storage_types();
+        if !storage_types.is_empty() {
+            let origin: String = self.eval_json("window.location.origin")?;
+            if origin != "null" {
+                let params = serde_json::json!({
+                    "origin": origin,
+                    "storageTypes": storage_types.join(","),
+                });
+                self.call_devtools_protocol_method(
+                    "Storage.clearDataForOrigin",
+                    &params.to_string(),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Answer HTTP Basic, Digest and NTLM authentication challenges instead of showing the
+    /// browser's login dialog. `f` receives the URI of the request, and returning
+    /// `Some((username, password))` sends those credentials while `None` cancels the request.
+    ///
+    /// This uses the DevTools Protocol `Fetch` domain in place of
+    /// `ICoreWebView2_10::add_BasicAuthenticationRequested`. It only pauses requests which get an
+    /// authentication challenge, so other requests are not slowed down.
+    pub fn on_basic_authentication_requested<F>(&self, mut f: F) -> Result<Subscription>
     where
-        F: FnOnce(WebView) + Send + 'static,
+        F: FnMut(&str) -> Option<(String, String)> + 'static,
     {
-        self.tx.send(Box::new(f)).expect("send the fn");
+        let webview = self.webview.clone();
+        let auth_required =
+            self.on_devtools_protocol_event("Fetch.authRequired", move |params| {
+                if let (Some(request_id), Some(Value::String(uri))) =
+                    (params.get("requestId"), params.pointer("/request/url"))
+                {
+                    let response = match f(uri) {
+                        Some((username, password)) => serde_json::json!({
+                            "response": "ProvideCredentials",
+                            "username": username,
+                            "password": password,
+                        }),
+                        None => serde_json::json!({ "response": "CancelAuth" }),
+                    };
+                    let params = serde_json::json!({
+                        "requestId": request_id,
+                        "authChallengeResponse": response,
+                    });
+                    let _ = post_devtools_protocol_method(
+                        &webview,
+                        "Fetch.continueWithAuth",
+                        &params.to_string(),
+                    );
+                }
+            })?;
 
+        let fetch = self.enable_fetch_domain()?;
+
+        Ok(Subscription::new(move || {
+            // Stop pausing requests before removing the handler which continues them.
+            drop(fetch);
+            drop(auth_required);
+            Ok(())
+        }))
+    }
+
+    /// Enable the DevTools Protocol `Fetch` domain to handle authentication challenges, without
+    /// pausing any other requests, until the returned [`Subscription`] is dropped. The domain is
+    /// shared by the whole page, so it stays enabled until every caller has dropped theirs.
+    fn enable_fetch_domain(&self) -> Result<Subscription> {
+        {
+            let mut fetch_users = self.fetch_users.lock()?;
+            if *fetch_users == 0 {
+                self.call_devtools_protocol_method(
+                    "Fetch.enable",
+                    r#"{"patterns":[],"handleAuthRequests":true}"#,
+                )?;
+            }
+            *fetch_users += 1;
+        }
+
+        let webview = self.webview.clone();
+        let fetch_users = self.fetch_users.clone();
+        Ok(Subscription::new(move || {
+            if let Ok(mut fetch_users) = fetch_users.lock() {
+                *fetch_users -= 1;
+                if *fetch_users == 0 {
+                    return post_devtools_protocol_method(&webview, "Fetch.disable", "{}");
+                }
+            }
+            Ok(())
+        }))
+    }
+
+    /// Decide what to do when a server presents an invalid TLS certificate, e.g. to allow a
+    /// self-signed certificate on a local development server.
+    ///
+    /// **Security:** returning [`CertAction::AlwaysAllow`] disables the protection TLS provides
+    /// against a man-in-the-middle for that request. Only allow errors for hosts you control, and
+    /// never for arbitrary sites. Without this callback, certificate errors always show the
+    /// default error page.
+    ///
+    /// This uses the DevTools Protocol `Security.certificateError` event in place of
+    /// `ICoreWebView2_14::add_ServerCertificateErrorDetected`. It is deprecated, but it is the only
+    /// way the protocol lets the host decide about each error. The certificate details come from
+    /// `Network.getCertificate`, and are empty if the runtime does not report the certificate.
+    /// [`CertAction::Default`] for a navigation cancels it and loads it again without this
+    /// handler, so the runtime shows its error page.
+    pub fn on_server_certificate_error<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(CertErrorInfo) -> CertAction + 'static,
+    {
+        // The document being navigated to, and the one to show the error page for once the
+        // canceled navigation has completed.
+        let navigation_uri = Arc::new(Mutex::new(None::<String>));
+        let error_page_uri = Arc::new(Mutex::new(None::<String>));
+        let restore_override = Arc::new(AtomicBool::new(false));
+
+        let weak = self.downgrade();
+        let handler_navigation_uri = navigation_uri.clone();
+        let handler_error_page_uri = error_page_uri.clone();
+        let certificate_error =
+            self.on_devtools_protocol_event("Security.certificateError", move |params| {
+                let event_id = params.get("eventId");
+                let error_type = params.get("errorType").and_then(Value::as_str);
+                let uri = params.get("requestURL").and_then(Value::as_str);
+                let webview = weak.upgrade();
+                if let (Some(event_id), Some(error_type), Some(uri), Some(webview)) =
+                    (event_id, error_type, uri, webview)
+                {
+                    let details = webview.certificate_details(uri).unwrap_or_default();
+                    let action = f(CertErrorInfo {
+                        error_type: String::from(error_type),
+                        request_uri: String::from(uri),
+                        subject: details.subject,
+                        issuer: details.issuer,
+                        thumbprint: details.thumbprint,
+                    });
+                    let is_document = match handler_navigation_uri.lock() {
+                        Ok(navigation_uri) => navigation_uri.as_deref() == Some(uri),
+                        Err(_) => false,
+                    };
+                    let (action, show_error_page) = action.devtools_response(is_document);
+                    if show_error_page {
+                        if let Ok(mut error_page_uri) = handler_error_page_uri.lock() {
+                            *error_page_uri = Some(String::from(uri));
+                        }
+                    }
+                    let params = serde_json::json!({
+                        "eventId": event_id,
+                        "action": action,
+                    });
+                    let _ = post_devtools_protocol_method(
+                        &webview.webview,
+                        "Security.handleCertificateError",
+                        &params.to_string(),
+                    );
+                }
+            })?;
+
+        let mut starting_token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_NavigationStarting(
+                callback::NavigationStartingEventHandler::create(Box::new(
+                    move |_webview, args| {
+                        if let Some(args) = args {
+                            let mut uri = PWSTR::default();
+                            args.get_Uri(&mut uri)?;
+                            if let Ok(mut navigation_uri) = navigation_uri.lock() {
+                                *navigation_uri = Some(pwstr::take_pwstr(uri));
+                            }
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut starting_token,
+            )?;
+        }
+
+        let mut completed_token = EventRegistrationToken::default();
         unsafe {
-            WindowsAndMessaging::PostThreadMessageA(
-                self.thread_id,
-                WindowsAndMessaging::WM_APP,
-                WPARAM(0),
-                LPARAM(0),
+            self.webview.add_NavigationCompleted(
+                callback::NavigationCompletedEventHandler::create(Box::new(
+                    move |webview, _args| {
+                        let webview = match webview {
+                            Some(webview) => webview,
+                            None => return Ok(()),
+                        };
+                        let error_page_uri = match error_page_uri.lock() {
+                            Ok(mut error_page_uri) => error_page_uri.take(),
+                            Err(_) => None,
+                        };
+                        if let Some(uri) = error_page_uri {
+                            // Both go through the same DevTools session, so the override is off
+                            // before the navigation starts.
+                            post_devtools_protocol_method(
+                                &webview,
+                                "Security.setOverrideCertificateErrors",
+                                r#"{"override":false}"#,
+                            )?;
+                            let params = serde_json::json!({ "url": uri });
+                            post_devtools_protocol_method(
+                                &webview,
+                                "Page.navigate",
+                                &params.to_string(),
+                            )?;
+                            restore_override.store(true, Ordering::SeqCst);
+                        } else if restore_override.swap(false, Ordering::SeqCst) {
+                            post_devtools_protocol_method(
+                                &webview,
+                                "Security.setOverrideCertificateErrors",
+                                r#"{"override":true}"#,
+                            )?;
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut completed_token,
+            )?;
+        }
+
+        self.call_devtools_protocol_method("Security.enable", "{}")?;
+        self.call_devtools_protocol_method(
+            "Security.setOverrideCertificateErrors",
+            r#"{"override":true}"#,
+        )?;
+
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || {
+            let result = post_devtools_protocol_method(
+                &webview,
+                "Security.setOverrideCertificateErrors",
+                r#"{"override":false}"#,
             );
+            unsafe {
+                webview.remove_NavigationStarting(starting_token)?;
+                webview.remove_NavigationCompleted(completed_token)?;
+            }
+            drop(certificate_error);
+            result
+        }))
+    }
+
+    /// Get the subject, issuer and thumbprint of the certificate the server for `uri` presented,
+    /// through the DevTools Protocol `Network.getCertificate` method.
+    fn certificate_details(&self, uri: &str) -> Result<certificate::Details> {
+        let origin = match uri.find("://") {
+            Some(scheme_end) => match uri[scheme_end + 3..].find('/') {
+                Some(path_start) => &uri[..scheme_end + 3 + path_start],
+                None => uri,
+            },
+            None => uri,
+        };
+        let params = serde_json::json!({ "origin": origin });
+        let result =
+            self.call_devtools_protocol_method("Network.getCertificate", &params.to_string())?;
+        Ok(result
+            .pointer("/tableNames/0")
+            .and_then(Value::as_str)
+            .and_then(decode_base64)
+            .and_then(|der| certificate::parse(&der))
+            .unwrap_or_default())
+    }
+
+    /// Call `f` with the level and text of each message the page writes to the console, e.g. to
+    /// include them in the app's log in a release build. Objects are described the way the
+    /// DevTools show them collapsed, e.g. `Array(3)`, and the arguments are joined with spaces.
+    ///
+    /// This enables the DevTools Protocol `Runtime` domain until the returned [`Subscription`] is
+    /// dropped, which makes the renderer send every console call to the host and keep the
+    /// logged objects alive for inspection, so pages which log heavily get slower and use more
+    /// memory. Dropping the subscription disables the domain again, including for other
+    /// subscribers to `Runtime` events.
+    pub fn on_console_message<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(ConsoleLevel, String) + 'static,
+    {
+        let console_api_called =
+            self.on_devtools_protocol_event("Runtime.consoleAPICalled", move |params| {
+                let level = ConsoleLevel::from_console_api_type(
+                    params
+                        .get("type")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default(),
+                );
+                let args = match params.get("args") {
+                    Some(Value::Array(args)) => args,
+                    _ => return,
+                };
+                let text = args
+                    .iter()
+                    .map(|arg| match (arg.get("value"), arg.get("description")) {
+                        (Some(Value::String(value)), _) => value.clone(),
+                        (Some(value), _) => value.to_string(),
+                        (None, Some(Value::String(description))) => description.clone(),
+                        (None, _) => match arg.get("unserializableValue") {
+                            Some(Value::String(value)) => value.clone(),
+                            _ => arg
+                                .get("type")
+                                .and_then(Value::as_str)
+                                .map(String::from)
+                                .unwrap_or_default(),
+                        },
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                f(level, text);
+            })?;
+
+        self.call_devtools_protocol_method("Runtime.enable", "{}")?;
+
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || {
+            let result = post_devtools_protocol_method(&webview, "Runtime.disable", "{}");
+            drop(console_api_called);
+            result
+        }))
+    }
+
+    pub fn open_dev_tools_window(&self) -> Result<&Self> {
+        unsafe { self.webview.OpenDevToolsWindow() }?;
+        Ok(self)
+    }
+
+    /// Call `f` whenever the favicon of the page changes, including when a new document with a
+    /// different favicon loads, e.g. to update the icon of a tab. Use [`WebView::get_favicon`]
+    /// to get the new image.
+    ///
+    /// This watches the `<link rel="icon">` elements of every new document with a
+    /// `MutationObserver` and reports changes through a binding named `__webviewFaviconChanged`,
+    /// so it only sees the icon the page declares, and not redirects or failed downloads.
+    pub fn on_favicon_changed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut() + 'static,
+    {
+        let mut last = None;
+        let js = format!(
+            r#"
+            (function() {{
+                var last = null;
+                var report = function() {{
+                    var uri = {};
+                    if (uri !== last) {{
+                        last = uri;
+                        window.{}(uri);
+                    }}
+                }};
+                var isLink = function(node) {{
+                    return node.nodeName === 'LINK';
+                }};
+                new MutationObserver(function(records) {{
+                    if (records.some(function(record) {{
+                        return isLink(record.target)
+                            || Array.prototype.some.call(record.addedNodes, isLink)
+                            || Array.prototype.some.call(record.removedNodes, isLink);
+                    }})) {{
+                        report();
+                    }}
+                }}).observe(document, {{
+                    subtree: true,
+                    childList: true,
+                    attributes: true,
+                    attributeFilter: ['href', 'rel'],
+                }});
+                document.addEventListener('DOMContentLoaded', report);
+            }})();"#,
+            FAVICON_URI_SCRIPT, FAVICON_CHANGED_BINDING
+        );
+        self.bind_event(
+            FAVICON_CHANGED_BINDING,
+            Box::new(move |_webview, params| {
+                if let Some(Value::String(uri)) = params.into_iter().next() {
+                    if last.as_ref() != Some(&uri) {
+                        last = Some(uri);
+                        f();
+                    }
+                }
+                Ok(Value::Null)
+            }),
+            &js,
+        )
+    }
+
+    /// Get the favicon of the current document encoded as `format`, from the last
+    /// `<link rel="icon">` element or the default `/favicon.ico` for the origin.
+    ///
+    /// The page loads the icon again and draws it on a canvas to encode it, so this returns
+    /// [`Error::ScriptError`] if the icon fails to load, including icons on another origin which
+    /// does not allow it with CORS. Transparent areas are white in an [`ImageFormat::Jpeg`].
+    pub fn get_favicon(&self, format: ImageFormat) -> Result<Vec<u8>> {
+        let js = format!(
+            r#"
+            new Promise(function(resolve, reject) {{
+                var uri = {};
+                var type = 'image/{}';
+                var image = new Image();
+                image.crossOrigin = 'anonymous';
+                image.onload = function() {{
+                    var canvas = document.createElement('canvas');
+                    canvas.width = image.naturalWidth || 16;
+                    canvas.height = image.naturalHeight || 16;
+                    var context = canvas.getContext('2d');
+                    if (type === 'image/jpeg') {{
+                        context.fillStyle = '#fff';
+                        context.fillRect(0, 0, canvas.width, canvas.height);
+                    }}
+                    context.drawImage(image, 0, 0, canvas.width, canvas.height);
+                    resolve(canvas.toDataURL(type));
+                }};
+                image.onerror = function() {{
+                    reject(new Error('failed to load the favicon from ' + uri));
+                }};
+                image.src = uri;
+            }})"#,
+            FAVICON_URI_SCRIPT,
+            format.devtools_value()
+        );
+        let params = serde_json::json!({
+            "expression": js,
+            "returnByValue": true,
+            "awaitPromise": true,
+        });
+        match self.evaluate(&params)? {
+            Value::String(data_uri) => data_uri
+                .splitn(2, ',')
+                .nth(1)
+                .and_then(decode_base64)
+                .ok_or_else(|| Error::ScriptError(String::from("invalid favicon data"))),
+            _ => Err(Error::ScriptError(String::from("missing favicon data"))),
+        }
+    }
+
+    /// Get the current DOM of the page serialized as HTML, including changes scripts have made
+    /// since it loaded, e.g. to archive or scrape it.
+    ///
+    /// Evaluating `document.documentElement.outerHTML` would be simpler, but `ExecuteScript`
+    /// cuts off results bigger than a few megabytes, so this uses the DevTools Protocol
+    /// `DOM.getDocument` and `DOM.getOuterHTML` methods instead, which return the complete HTML
+    /// of the document, including the doctype.
+    pub fn outer_html(&self) -> Result<String> {
+        let document = self.call_devtools_protocol_method("DOM.getDocument", r#"{"depth":0}"#)?;
+        let node_id = document
+            .pointer("/root/nodeId")
+            .cloned()
+            .ok_or_else(|| Error::DevToolsProtocolError(String::from("missing document node")))?;
+        let params = serde_json::json!({ "nodeId": node_id });
+        match self
+            .call_devtools_protocol_method("DOM.getOuterHTML", &params.to_string())?
+            .get_mut("outerHTML")
+            .map(Value::take)
+        {
+            Some(Value::String(html)) => Ok(html),
+            _ => Err(Error::DevToolsProtocolError(String::from(
+                "missing outer HTML",
+            ))),
+        }
+    }
+
+    /// Scroll the page so the point `x`, `y` in CSS pixels is at the top left of the viewport,
+    /// e.g. `scroll_to(0.0, 0.0)` for a "scroll to top" button. Positions past the end of the
+    /// page are clamped, and a page which does not scroll ignores this.
+    pub fn scroll_to(&self, x: f64, y: f64) -> Result<&Self> {
+        // Serialize through `Value` so non-finite numbers become `null` instead of invalid script.
+        self.eval(&format!(
+            "window.scrollTo({}, {})",
+            Value::from(x),
+            Value::from(y)
+        ))
+    }
+
+    /// Search the text of the page for `query`, like Ctrl+F in a browser, and select the first
+    /// match. Call [`WebView::find_next`] and [`WebView::find_previous`] to step through the
+    /// matches, wrapping around at either end, and [`WebView::stop_find`] when the search UI
+    /// closes. An empty `query` clears the previous search.
+    ///
+    /// Without `ICoreWebView2Find`, and with no find in page in the DevTools Protocol, this
+    /// searches the visible text with a script. The active match is selected and scrolled into
+    /// view, and all of the matches are highlighted in yellow if the runtime supports the CSS
+    /// Custom Highlight API. Matches spanning several elements, e.g. half in a `<b>`, are not
+    /// found. The search only covers the current document, so search again after a navigation.
+    pub fn find(&self, query: &str, options: FindOptions) -> Result<FindResult> {
+        self.eval_json(&format!(
+            "{}\nwindow.__webviewFind.find({}, {}, {})",
+            FIND_SCRIPT,
+            serde_json::to_string(query)?,
+            options.case_sensitive,
+            options.whole_word
+        ))
+    }
+
+    /// Select the next match of the last [`WebView::find`], or the first one after the last.
+    pub fn find_next(&self) -> Result<FindResult> {
+        self.find_step(1)
+    }
+
+    /// Select the previous match of the last [`WebView::find`], or the last one before the
+    /// first.
+    pub fn find_previous(&self) -> Result<FindResult> {
+        self.find_step(-1)
+    }
+
+    /// Clear the selection and highlights of the last [`WebView::find`].
+    pub fn stop_find(&self) -> Result<&Self> {
+        self.eval(&format!("{}\nwindow.__webviewFind.stop()", FIND_SCRIPT))
+    }
+
+    fn find_step(&self, delta: i32) -> Result<FindResult> {
+        self.eval_json(&format!(
+            "{}\nwindow.__webviewFind.step({})",
+            FIND_SCRIPT, delta
+        ))
+    }
+
+    /// Scroll the page by `dx`, `dy` in CSS pixels from where it is now, like
+    /// [`WebView::scroll_to`].
+    pub fn scroll_by(&self, dx: f64, dy: f64) -> Result<&Self> {
+        self.eval(&format!(
+            "window.scrollBy({}, {})",
+            Value::from(dx),
+            Value::from(dy)
+        ))
+    }
+
+    /// Type `text` into the focused element of the page, e.g. in an end-to-end test, and return
+    /// once it has been inserted. This calls the DevTools Protocol `Input.insertText` method,
+    /// which fires `input` events like an IME would, but no key events; use
+    /// [`WebView::send_key`] for a page which listens for those.
+    ///
+    /// Focusing the right element is up to the caller, e.g. with
+    /// `webview.eval("document.querySelector('#name').focus()")`, which waits for the script to
+    /// run. The `WebView` itself does not need keyboard focus.
+    pub fn type_text(&self, text: &str) -> Result<&Self> {
+        let params = serde_json::json!({ "text": text });
+        self.call_devtools_protocol_method("Input.insertText", &params.to_string())?;
+        Ok(self)
+    }
+
+    /// Press and release `key` with `modifiers` held down in the focused element of the page,
+    /// e.g. [`Key::Enter`] to submit a form or `Key::Character('a')` with [`Modifiers::CONTROL`]
+    /// to select everything. Focus the element first as for [`WebView::type_text`].
+    ///
+    /// This calls the DevTools Protocol `Input.dispatchKeyEvent` method, which fires `keydown`,
+    /// `keypress` and `keyup` events and runs the default action of the key, but not browser
+    /// accelerators such as Ctrl+P.
+    pub fn send_key(&self, key: Key, modifiers: Modifiers) -> Result<&Self> {
+        let (key, code, vk, text) = key.devtools_values();
+        // Keys typed with Ctrl, Alt or the Windows key held down run a command instead of typing.
+        let text = text.filter(|_| {
+            !modifiers.contains(Modifiers::CONTROL)
+                && !modifiers.contains(Modifiers::ALT)
+                && !modifiers.contains(Modifiers::META)
+        });
+        let mut params = serde_json::json!({
+            "type": if text.is_some() { "keyDown" } else { "rawKeyDown" },
+            "modifiers": modifiers.0,
+            "key": key,
+            "code": code,
+            "windowsVirtualKeyCode": vk,
+        });
+        if let Some(text) = text {
+            params["text"] = Value::from(text);
+        }
+        self.call_devtools_protocol_method("Input.dispatchKeyEvent", &params.to_string())?;
+
+        params["type"] = Value::from("keyUp");
+        if let Value::Object(params) = &mut params {
+            params.remove("text");
+        }
+        self.call_devtools_protocol_method("Input.dispatchKeyEvent", &params.to_string())?;
+        Ok(self)
+    }
+
+    /// Click `button` at `x`, `y`, e.g. in an end-to-end test, by moving the mouse there and
+    /// pressing and releasing the button, which fires the same events as a real click.
+    ///
+    /// This calls the DevTools Protocol `Input.dispatchMouseEvent` method, so `x` and `y` are in
+    /// CSS pixels relative to the top left of the viewport, the same as `clientX` and `clientY`
+    /// in the page, e.g. from `getBoundingClientRect()`. They are neither client coordinates of
+    /// the window, which are scaled by the device pixel ratio, nor relative to the document like
+    /// the `rect` passed to [`WebView::capture_region`], which differs by the scroll position.
+    pub fn click(&self, x: f64, y: f64, button: MouseButton) -> Result<&Self> {
+        self.move_mouse(x, y)?;
+        for event in ["mousePressed", "mouseReleased"].iter() {
+            let params = serde_json::json!({
+                "type": event,
+                "x": x,
+                "y": y,
+                "button": button.devtools_value(),
+                "clickCount": 1,
+            });
+            self.call_devtools_protocol_method("Input.dispatchMouseEvent", &params.to_string())?;
+        }
+        Ok(self)
+    }
+
+    /// Move the mouse to `x`, `y` without pressing a button, e.g. to open a menu which shows on
+    /// hover. The coordinates are in the same space as for [`WebView::click`].
+    pub fn move_mouse(&self, x: f64, y: f64) -> Result<&Self> {
+        let params = serde_json::json!({
+            "type": "mouseMoved",
+            "x": x,
+            "y": y,
+        });
+        self.call_devtools_protocol_method("Input.dispatchMouseEvent", &params.to_string())?;
+        Ok(self)
+    }
+
+    /// Check whether the current document is playing audio, e.g. to poll for a "now playing"
+    /// indicator.
+    ///
+    /// Without `ICoreWebView2_8::get_IsDocumentPlayingAudio`, this checks for `<audio>` and
+    /// `<video>` elements which are playing and not muted. Sound from the Web Audio API or from
+    /// child frames is not detected.
+    pub fn is_document_playing_audio(&self) -> Result<bool> {
+        self.eval_json(
+            r#"
+            Array.prototype.some.call(document.querySelectorAll('audio, video'), function(media) {
+                return !media.paused && !media.ended && !media.muted && media.volume > 0;
+            })"#,
+        )
+    }
+
+    /// Mute or unmute the audio of every page this `WebView` loads from now on, including the
+    /// current one, e.g. for a mute button in a media app.
+    ///
+    /// Instead of `ICoreWebView2_8::put_IsMuted`, which runtimes before 1.0.1072.54 also reset on
+    /// every navigation, this adds an init script which mutes `<audio>` and `<video>` elements
+    /// whenever they start playing or the page changes their volume, so it applies to each new
+    /// document before its own scripts run. Every navigation also mutes the elements the new
+    /// document has once it completes. Sound from the Web Audio API is not muted.
+    pub fn set_muted(&self, muted: bool) -> Result<&Self> {
+        // Don't hold the lock while adding or running scripts, since that processes messages,
+        // which may call `is_muted`, e.g. from `on_audio_state_changed`.
+        let mute_script = self.mute_script.lock()?.take();
+        let (mute_script, js) = match (muted, mute_script) {
+            (true, Some(id)) => (Some(id), None),
+            (true, None) => (
+                Some(self.add_script(MUTE_SCRIPT)?),
+                Some(format!("{}{}", MUTE_SCRIPT, MUTE_MEDIA_SCRIPT)),
+            ),
+            (false, Some(id)) => {
+                self.remove_init_script(&id)?;
+                (
+                    None,
+                    Some(String::from(
+                        r#"
+                        window.__webviewMuted = false;
+                        document.querySelectorAll('audio, video').forEach(function(media) {
+                            if (media.__webviewMutedByHost) {
+                                media.__webviewMutedByHost = false;
+                                media.muted = false;
+                            }
+                        });"#,
+                    )),
+                )
+            }
+            (false, None) => (None, None),
+        };
+        *self.mute_script.lock()? = mute_script;
+
+        if let Some(js) = js {
+            self.eval(&format!(
+                r#"
+                {}
+                if (window.{}) {{
+                    window.{}();
+                }}"#,
+                js, REPORT_AUDIO_STATE, REPORT_AUDIO_STATE
+            ))?;
+        }
+        Ok(self)
+    }
+
+    /// Check whether [`WebView::set_muted`] is muting the audio.
+    pub fn is_muted(&self) -> Result<bool> {
+        Ok(self.mute_script.lock()?.is_some())
+    }
+
+    /// Call `f` with the [`AudioState`] whenever [`WebView::set_muted`] changes it or the page
+    /// starts or stops playing audio, e.g. to update a speaker icon. Changes which cancel each
+    /// other out before the page reports them, e.g. `pause` followed by `play`, are dropped, and
+    /// `f` is never called twice in a row with the same state.
+    ///
+    /// Instead of the `IsMutedChanged` and `IsDocumentPlayingAudioChanged` events of
+    /// `ICoreWebView2_8`, this listens for the media events of `<audio>` and `<video>` elements
+    /// through a binding named `__webviewAudioStateChanged`, with the same limits as
+    /// [`WebView::is_document_playing_audio`].
+    pub fn on_audio_state_changed<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(AudioState) + 'static,
+    {
+        let mut last = None;
+        let js = format!(
+            r#"
+            (function() {{
+                var playing = null;
+                var report = function() {{
+                    var now = Array.prototype.some.call(
+                        document.querySelectorAll('audio, video'),
+                        function(media) {{
+                            return !media.paused && !media.ended && media.volume > 0
+                                && (!media.muted || window.__webviewMuted === true);
+                        }});
+                    if (now !== playing) {{
+                        playing = now;
+                        window.{}(now);
+                    }}
+                }};
+                var pending = false;
+                var schedule = function() {{
+                    if (!pending) {{
+                        pending = true;
+                        setTimeout(function() {{
+                            pending = false;
+                            report();
+                        }});
+                    }}
+                }};
+                window.{} = function() {{
+                    playing = null;
+                    report();
+                }};
+                ['play', 'playing', 'pause', 'ended', 'emptied', 'volumechange'].forEach(
+                    function(type) {{
+                        document.addEventListener(type, schedule, true);
+                    }});
+                document.addEventListener('DOMContentLoaded', report);
+            }})();"#,
+            AUDIO_STATE_BINDING, REPORT_AUDIO_STATE
+        );
+        self.bind_event(
+            AUDIO_STATE_BINDING,
+            Box::new(move |webview, params| {
+                if let Some(Value::Bool(playing)) = params.first() {
+                    let state = AudioState {
+                        muted: webview.is_muted()?,
+                        playing: *playing,
+                    };
+                    if last != Some(state) {
+                        last = Some(state);
+                        f(state);
+                    }
+                }
+                Ok(Value::Null)
+            }),
+            &js,
+        )
+    }
+
+    /// Call `f` with messages the page sends with `window.chrome.webview.postMessage`.
+    ///
+    /// Messages which look like a call to a function registered with [`WebView::bind`], i.e.
+    /// an object with `id`, `method` and `params` where `method` is a bound name, are routed to
+    /// that binding and never reach `f`, so avoid that shape for other messages.
+    pub fn on_web_message<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(Value) + 'static,
+    {
+        let bindings = self.bindings.clone();
+        let allowed_origins = self.web_message_allowed_origins.clone();
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            self.webview.add_WebMessageReceived(
+                callback::WebMessageReceivedEventHandler::create(Box::new(
+                    move |_webview, args| {
+                        if let Some(args) = args {
+                            if !is_allowed_source(&allowed_origins, &args)? {
+                                return Ok(());
+                            }
+                            let mut message = PWSTR::default();
+                            args.get_WebMessageAsJson(&mut message)?;
+                            let message = pwstr::take_pwstr(message);
+                            if let Ok(value) = serde_json::from_str::<Value>(&message) {
+                                let is_invoke = match InvokeMessage::deserialize(&value) {
+                                    Ok(invoke) => match bindings.lock() {
+                                        Ok(bindings) => bindings.contains_key(&invoke.method),
+                                        Err(_) => true,
+                                    },
+                                    Err(_) => false,
+                                };
+                                if !is_invoke {
+                                    f(value);
+                                }
+                            }
+                        }
+                        Ok(())
+                    },
+                )),
+                &mut token,
+            )?;
+        }
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || unsafe {
+            webview.remove_WebMessageReceived(token)
+        }))
+    }
+
+    /// Only accept messages from documents with one of these `origins`, e.g.
+    /// `https://example.com`, both for [`WebView::bind`] and [`WebView::on_web_message`].
+    /// Messages from other origins are dropped and logged. An empty list, which is the default,
+    /// accepts messages from every origin.
+    pub fn set_web_message_allowed_origins(&self, origins: Vec<String>) -> Result<&Self> {
+        *self.web_message_allowed_origins.lock()? = origins;
+        Ok(self)
+    }
+
+    /// Enable or disable `window.chrome.webview.postMessage` altogether. This also disables
+    /// [`WebView::bind`], which relies on it.
+    pub fn set_web_message_enabled(&self, enabled: bool) -> Result<&Self> {
+        unsafe { self.settings.put_IsWebMessageEnabled(enabled) }?;
+        Ok(self)
+    }
+
+    /// Post a JSON message to the page, which it can receive with
+    /// `window.chrome.webview.addEventListener('message', e => ...)` as `e.data`.
+    pub fn post_web_message_json(&self, value: &Value) -> Result<&Self> {
+        let json = value.to_string();
+        unsafe { self.webview.PostWebMessageAsJson(json) }?;
+        Ok(self)
+    }
+
+    /// Post a string message to the page, which it can receive with
+    /// `window.chrome.webview.addEventListener('message', e => ...)` as `e.data`.
+    pub fn post_web_message_string(&self, message: &str) -> Result<&Self> {
+        unsafe { self.webview.PostWebMessageAsString(message) }?;
+        Ok(self)
+    }
+
+    /// Get a [`WeakWebView`] which callbacks can capture without keeping the `WebView` alive.
+    pub fn downgrade(&self) -> WeakWebView {
+        WeakWebView(Arc::downgrade(&self.inner))
+    }
+
+    /// Get a handle which can be sent to other threads to [`WebViewHandle::dispatch`] work
+    /// back to this `WebView`.
+    pub fn handle(&self) -> WebViewHandle {
+        WebViewHandle {
+            tx: Arc::new(Mutex::new(self.tx.clone())),
+            dispatch_event: self.dispatch_event.clone(),
+            thread_id: self.thread_id,
         }
+    }
+
+    pub fn dispatch<F>(&self, f: F) -> Result<&Self>
+    where
+        F: FnOnce(WebView) + Send + 'static,
+    {
+        self.tx.send(Box::new(f)).expect("send the fn");
+        wake_thread(&self.dispatch_event, self.thread_id);
         Ok(self)
     }
 
-    pub fn bind<F>(&self, name: &str, f: F) -> Result<&Self>
+    pub fn bind<F>(&self, name: &str, mut f: F) -> Result<&Self>
     where
         F: FnMut(Vec<Value>) -> Result<Value> + 'static,
     {
-        self.bindings
-            .lock()?
-            .insert(String::from(name), Box::new(f));
+        self.bind_callback(name, Box::new(move |_webview, params| f(params)))?;
+        Ok(self)
+    }
+
+    /// Same as [`WebView::bind`], but the `WebView` owns `context` and passes it to `f` along
+    /// with the `WebView` itself, instead of `f` capturing shared state and a clone of the
+    /// `WebView` on its own.
+    ///
+    /// `f` may call back into the `WebView`, e.g. to `eval` a script or `bind` another function.
+    /// If that processes messages and the page calls the same function again in the meantime,
+    /// the nested call is dropped rather than re-entering `f`.
+    pub fn bind_with_context<C, F>(&self, name: &str, mut context: C, mut f: F) -> Result<&Self>
+    where
+        C: 'static,
+        F: FnMut(&mut C, &WebView, Vec<Value>) -> Result<Value> + 'static,
+    {
+        self.bind_callback(
+            name,
+            Box::new(move |webview, params| f(&mut context, webview, params)),
+        )?;
+        Ok(self)
+    }
+
+    /// Register `f` as the binding `name` and return the ID of the init script which defines it
+    /// in the page.
+    fn bind_callback(&self, name: &str, f: BindingCallback) -> Result<String> {
+        self.bindings.lock()?.insert(String::from(name), f);
 
         let js = String::from(
             r#"
@@ -528,19 +5178,108 @@ impl WebView {
                     });
                     return promise;
                 }
-            })()"#;
+            })()"#;
+
+        self.add_init_script(&js)
+    }
+
+    /// Register `f` as the binding `name` and add `js`, which calls it from every new document to
+    /// report an event. Dropping the returned [`Subscription`] removes both again.
+    fn bind_event(&self, name: &str, f: BindingCallback, js: &str) -> Result<Subscription> {
+        let script_ids = [self.bind_callback(name, f)?, self.add_init_script(js)?];
+
+        let webview = self.webview.clone();
+        let bindings = self.bindings.clone();
+        let init_scripts = self.init_scripts.clone();
+        let name = String::from(name);
+        Ok(Subscription::new(move || {
+            if let Ok(mut bindings) = bindings.lock() {
+                bindings.remove(&name);
+            }
+            if let Ok(mut init_scripts) = init_scripts.lock() {
+                init_scripts.retain(|(id, _)| !script_ids.contains(id));
+            }
+            for id in script_ids.iter() {
+                unsafe { webview.RemoveScriptToExecuteOnDocumentCreated(id.as_str()) }?;
+            }
+            Ok(())
+        }))
+    }
+
+    /// Replace the default context menu with one the host shows itself, e.g. with
+    /// `TrackPopupMenu`. Once this is registered, the default menu is suppressed in every new
+    /// document and `f` receives what was under the pointer instead, until the returned
+    /// [`Subscription`] is dropped.
+    ///
+    /// Instead of `ICoreWebView2_11::add_ContextMenuRequested`, this listens for the `contextmenu`
+    /// DOM event through a binding named `__webviewContextMenuRequested`. Since the binding is
+    /// asynchronous, the page cannot wait for the callback to decide whether to show the default
+    /// menu, and it does not apply to a page which calls `preventDefault` itself.
+    ///
+    /// This is only part of what the native event offers. `f` does not get the items of the
+    /// default menu and returns nothing, so it cannot keep the default menu for some elements, and
+    /// the host runs the command the user picks from its own menu instead of reporting it back to
+    /// the runtime. Those need `ICoreWebView2ContextMenuRequestedEventArgs`, which is missing
+    /// from these bindings.
+    pub fn on_context_menu_requested<F>(&self, mut f: F) -> Result<Subscription>
+    where
+        F: FnMut(ContextMenuContext) + 'static,
+    {
+        let js = format!(
+            r#"
+            window.{} = function(e) {{
+                if (e.defaultPrevented) {{
+                    return;
+                }}
+                e.preventDefault();
+                var target = e.target;
+                var link = target.closest ? target.closest('a[href]') : null;
+                window.{}({{
+                    x: e.clientX,
+                    y: e.clientY,
+                    linkUri: link ? link.href : null,
+                    imageUri: target instanceof HTMLImageElement ? target.src : null,
+                    selectionText: String(window.getSelection()) || null,
+                    isEditable: !!target.isContentEditable
+                        || target instanceof HTMLInputElement
+                        || target instanceof HTMLTextAreaElement,
+                }});
+            }};
+            window.addEventListener('contextmenu', window.{});"#,
+            CONTEXT_MENU_LISTENER, CONTEXT_MENU_BINDING, CONTEXT_MENU_LISTENER
+        );
+        let binding = self.bind_event(
+            CONTEXT_MENU_BINDING,
+            Box::new(move |_webview, params| {
+                if let Some(context) = params.into_iter().next() {
+                    f(serde_json::from_value(context)?);
+                }
+                Ok(Value::Null)
+            }),
+            &js,
+        )?;
 
-        self.init(&js)
+        let webview = self.webview.clone();
+        Ok(Subscription::new(move || {
+            // The current document keeps its listener, so remove it to show the default menu.
+            let params = serde_json::json!({
+                "expression": format!(
+                    "window.removeEventListener('contextmenu', window.{})",
+                    CONTEXT_MENU_LISTENER
+                ),
+            });
+            let result =
+                post_devtools_protocol_method(&webview, "Runtime.evaluate", &params.to_string());
+            drop(binding);
+            result
+        }))
     }
 
-    pub fn resolve(&self, id: u64, status: i32, result: Value) -> Result<&Self> {
+    pub fn resolve(&self, id: u64, status: PromiseStatus, result: Value) -> Result<&Self> {
         let result = result.to_string();
 
         self.dispatch(move |webview| {
-            let method = match status {
-                0 => "resolve",
-                _ => "reject",
-            };
+            let method = status.method();
             let js = format!(
                 r#"
                 window._rpc[{}].{}({});
@@ -552,7 +5291,13 @@ impl WebView {
         })
     }
 
-    fn set_window_webview(hwnd: HWND, webview: Option<Box<WebView>>) -> Option<Box<WebView>> {
+    /// Store a weak handle to the `WebView` in the frame window for `window_proc`, so the window
+    /// does not keep the `WebView` or the message loop alive after the app has dropped all of
+    /// its own clones.
+    fn set_window_webview(
+        hwnd: HWND,
+        webview: Option<Box<WeakWebView>>,
+    ) -> Option<Box<WeakWebView>> {
         unsafe {
             match SetWindowLong(
                 hwnd,
@@ -568,30 +5313,498 @@ impl WebView {
         }
     }
 
-    fn get_window_webview(hwnd: HWND) -> Option<Box<WebView>> {
+    fn get_window_webview(hwnd: HWND) -> Option<WebView> {
         unsafe {
             let data = GetWindowLong(hwnd, WindowsAndMessaging::GWLP_USERDATA);
 
             match data {
                 0 => None,
                 _ => {
-                    let webview_ptr = data as *mut WebView;
+                    let webview_ptr = data as *mut WeakWebView;
                     let raw = Box::from_raw(webview_ptr);
-                    let webview = raw.clone();
+                    let webview = raw.upgrade();
                     mem::forget(raw);
 
-                    Some(webview)
+                    webview
                 }
             }
         }
     }
 }
 
+/// Get the version of the installed WebView2 runtime, e.g. `"94.0.992.31"`, to check whether it
+/// is new enough before calling [`WebView::create`]. Returns [`Error::RuntimeNotInstalled`] if
+/// the runtime is missing.
+pub fn available_browser_version() -> Result<String> {
+    let mut version = PWSTR::default();
+    match unsafe { GetAvailableCoreWebView2BrowserVersionString(PWSTR::default(), &mut version) } {
+        Ok(()) => match pwstr::take_pwstr(version) {
+            version if version.is_empty() => Err(Error::RuntimeNotInstalled),
+            version => Ok(version),
+        },
+        Err(err) if err.code() == HRESULT::from_win32(ERROR_FILE_NOT_FOUND.0) => {
+            Err(Error::RuntimeNotInstalled)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Compare two WebView2 runtime version strings, e.g. the result of
+/// [`available_browser_version`] and the minimum version the app needs.
+pub fn compare_browser_versions(a: &str, b: &str) -> Result<std::cmp::Ordering> {
+    let mut result = 0;
+    unsafe { CompareBrowserVersions(a, b, &mut result) }?;
+    Ok(result.cmp(&0))
+}
+
+/// Open `url` in a new frame window and run the message loop until the window is closed, e.g.
+/// for quick tools and examples. This initializes COM for the calling thread and makes the process
+/// DPI aware first, so it can be the only call in `main`.
+///
+/// ```ignore
+/// fn main() -> Result<()> {
+///     open("https://github.com/microsoft/windows-rs")
+/// }
+/// ```
+pub fn open(url: &str) -> Result<()> {
+    init_ui_thread()?;
+    WebViewBuilder::new().with_url(url).build()?.run()
+}
+
+/// Like [`open`], but set the title of the frame window to `title`.
+pub fn open_with_title(title: &str, url: &str) -> Result<()> {
+    init_ui_thread()?;
+    let webview = WebViewBuilder::new().with_url(url).build()?;
+    webview.set_title(title)?;
+    webview.run()
+}
+
+fn init_ui_thread() -> Result<()> {
+    unsafe {
+        CoInitializeEx(std::ptr::null_mut(), COINIT_APARTMENTTHREADED)?;
+    }
+    // This fails if the DPI awareness has already been set, e.g. in the app manifest or by an
+    // earlier call, which is fine.
+    let _ = set_process_dpi_awareness();
+    Ok(())
+}
+
+/// Get the work area, i.e. without the taskbar, of the monitor at `index` in the order
+/// `EnumDisplayMonitors` lists them.
+fn monitor_work_area(index: usize) -> Result<RECT> {
+    extern "system" fn enum_monitor(
+        monitor: Gdi::HMONITOR,
+        _hdc: Gdi::HDC,
+        _rect: *mut RECT,
+        data: LPARAM,
+    ) -> BOOL {
+        let monitors = unsafe { &mut *(data.0 as *mut Vec<Gdi::HMONITOR>) };
+        monitors.push(monitor);
+        true.into()
+    }
+
+    let mut monitors: Vec<Gdi::HMONITOR> = Vec::new();
+    unsafe {
+        Gdi::EnumDisplayMonitors(
+            None,
+            ptr::null(),
+            Some(enum_monitor),
+            LPARAM(&mut monitors as *mut _ as isize),
+        );
+    }
+    let monitor = *monitors.get(index).ok_or(Error::MonitorNotFound(index))?;
+
+    let mut info = Gdi::MONITORINFO {
+        cbSize: mem::size_of::<Gdi::MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    if !unsafe { Gdi::GetMonitorInfoW(monitor, &mut info) }.as_bool() {
+        return Err(windows::Error::from_win32().into());
+    }
+    Ok(info.rcWork)
+}
+
+/// Check whether the process with `process_id` still exists.
+fn is_process_running(process_id: u32) -> bool {
+    let process = unsafe {
+        Threading::OpenProcess(
+            Threading::PROCESS_QUERY_LIMITED_INFORMATION,
+            false,
+            process_id,
+        )
+    };
+    if process.0 == 0 {
+        return false;
+    }
+    unsafe { CloseHandle(process) };
+    true
+}
+
+/// Turn the error `CreateCoreWebView2Environment` reports when it cannot find a runtime into
+/// [`Error::RuntimeNotInstalled`], which tells the user what to do instead of just a `HRESULT`.
+fn environment_error(err: windows::Error) -> Error {
+    if err.code() == HRESULT::from_win32(ERROR_FILE_NOT_FOUND.0) {
+        logger::log(format_args!(
+            "WebView2 runtime not found, install it from \
+             https://go.microsoft.com/fwlink/p/?LinkId=2124703"
+        ));
+        Error::RuntimeNotInstalled
+    } else {
+        Error::WindowsError(err)
+    }
+}
+
 fn set_process_dpi_awareness() -> Result<()> {
     unsafe { HiDpi::SetProcessDpiAwareness(HiDpi::PROCESS_PER_MONITOR_DPI_AWARE)? };
     Ok(())
 }
 
+/// Call a DevTools Protocol method without waiting for the result, e.g. to answer an event from
+/// inside its handler without pumping messages there.
+fn post_devtools_protocol_method(
+    webview: &ICoreWebView2,
+    method: &str,
+    params_json: &str,
+) -> windows::Result<()> {
+    unsafe {
+        webview.CallDevToolsProtocolMethod(
+            method,
+            params_json,
+            callback::CallDevToolsProtocolMethodCompletedHandler::create(Box::new(
+                |_error_code, _result| Ok(()),
+            )),
+        )
+    }
+}
+
+fn is_key_down(virtual_key: i32) -> bool {
+    unsafe { KeyboardAndMouseInput::GetKeyState(virtual_key) < 0 }
+}
+
+/// Get the user data folder for a named profile, alongside the default `<app>.exe.WebView2`.
+fn profile_user_data_folder(name: &str) -> Result<String> {
+    let is_valid_char = |c: char| c.is_ascii_alphanumeric() || c == ' ' || "#@$()+-_~.".contains(c);
+    if name.is_empty()
+        || name.len() > 64
+        || !name.chars().all(is_valid_char)
+        || name.ends_with(' ')
+        || name.ends_with('.')
+    {
+        return Err(Error::InvalidProfileName(String::from(name)));
+    }
+
+    let mut folder = std::env::current_exe()?.into_os_string();
+    folder.push(".WebView2.");
+    folder.push(name);
+    Ok(folder.to_string_lossy().into_owned())
+}
+
+/// Search a `FrameTree` from `Page.getFrameTree` for the frame named `name` and return its ID.
+fn find_frame_id(frame_tree: &Value, name: &str) -> Option<String> {
+    let frame = &frame_tree["frame"];
+    if frame["name"].as_str() == Some(name) {
+        return frame["id"].as_str().map(String::from);
+    }
+    frame_tree["childFrames"]
+        .as_array()?
+        .iter()
+        .find_map(|child| find_frame_id(child, name))
+}
+
+/// Check whether the document which sent a web message is in `allowed_origins`, which allows
+/// every origin if it is empty.
+fn is_allowed_source(
+    allowed_origins: &Mutex<Vec<String>>,
+    args: &ICoreWebView2WebMessageReceivedEventArgs,
+) -> windows::Result<bool> {
+    let allowed_origins = match allowed_origins.lock() {
+        Ok(allowed_origins) => allowed_origins,
+        Err(_) => return Ok(false),
+    };
+    if allowed_origins.is_empty() {
+        return Ok(true);
+    }
+
+    let mut source = PWSTR::default();
+    unsafe { args.get_Source(&mut source) }?;
+    let source = pwstr::take_pwstr(source);
+    let allowed = allowed_origins
+        .iter()
+        .any(|origin| origin.trim_end_matches('/') == origin_of(&source));
+    if !allowed {
+        logger::log(format_args!("web message dropped from {}", source));
+    }
+    Ok(allowed)
+}
+
+/// Get the `scheme://host:port` origin of `uri`, e.g. `https://example.com` for
+/// `https://example.com/path?query`.
+fn origin_of(uri: &str) -> &str {
+    match uri.find("://") {
+        Some(scheme_end) => {
+            let authority = scheme_end + 3;
+            match uri[authority..].find(|c| c == '/' || c == '?' || c == '#') {
+                Some(path) => &uri[..authority + path],
+                None => uri,
+            }
+        }
+        None => uri,
+    }
+}
+
+/// Collect the name and value of every header from `iterator`.
+fn collect_headers(
+    iterator: ICoreWebView2HttpHeadersCollectionIterator,
+) -> windows::Result<Vec<(String, String)>> {
+    let mut headers = Vec::new();
+    let mut has_current = BOOL::default();
+    unsafe {
+        iterator.get_HasCurrentHeader(&mut has_current)?;
+        while has_current.as_bool() {
+            let mut name = PWSTR::default();
+            let mut value = PWSTR::default();
+            iterator.GetCurrentHeader(&mut name, &mut value)?;
+            headers.push((pwstr::take_pwstr(name), pwstr::take_pwstr(value)));
+            iterator.MoveNext(&mut has_current)?;
+        }
+    }
+    Ok(headers)
+}
+
+/// Join HTTP headers into the `name: value` lines WebView2 expects.
+fn format_headers<'a>(headers: impl Iterator<Item = (&'a String, &'a String)>) -> String {
+    headers
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect()
+}
+
+/// Copy `data` into a new memory `IStream`, e.g. for a request or response body.
+fn create_mem_stream(data: &[u8]) -> Result<IStream> {
+    unsafe { Shell::SHCreateMemStream(data.as_ptr(), data.len() as u32) }
+        .ok_or_else(|| windows::Error::fast_error(E_OUTOFMEMORY).into())
+}
+
+/// Decode standard, padded base64, e.g. binary data returned by the DevTools Protocol.
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+            b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+
+    let data = data.trim_end_matches('=').as_bytes();
+    let mut result = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut bits = 0;
+        for (i, c) in chunk.iter().enumerate() {
+            bits |= value(*c)? << (18 - 6 * i);
+        }
+        let bytes = [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+        result.extend_from_slice(&bytes[..chunk.len() - 1]);
+    }
+    Some(result)
+}
+
+/// Read everything which is left in `stream`.
+fn read_stream(stream: &IStream) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    let mut buffer = [0u8; 4096];
+    loop {
+        let mut read = 0;
+        unsafe {
+            stream.Read(
+                buffer.as_mut_ptr() as *mut _,
+                buffer.len() as u32,
+                &mut read,
+            )
+        }?;
+        if read == 0 {
+            break;
+        }
+        content.extend_from_slice(&buffer[..read as usize]);
+    }
+    Ok(content)
+}
+
+/// Check that `tag` looks like a BCP 47 language tag, e.g. `en`, `fr-FR` or `zh-Hant-TW`: a 2 or
+/// 3 letter language followed by subtags of 1 to 8 letters or digits, separated by hyphens.
+fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let language = subtags.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// The largest string `NavigateToString` accepts, which [`WebView::load_html`] works around.
+const NAVIGATE_TO_STRING_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Mutes media elements in each document while [`WebView::set_muted`] is on. `play` and
+/// `volumechange` do not bubble, so listen for them in the capture phase.
+const MUTE_SCRIPT: &str = r#"
+(function() {
+    window.__webviewMuted = true;
+    var mute = function(e) {
+        if (window.__webviewMuted && e.target instanceof HTMLMediaElement && !e.target.muted) {
+            e.target.muted = true;
+            e.target.__webviewMutedByHost = true;
+        }
+    };
+    document.addEventListener('play', mute, true);
+    document.addEventListener('volumechange', mute, true);
+})();"#;
+
+/// Mutes the `<audio>` and `<video>` elements the current document already has, for
+/// [`WebView::set_muted`] and again after each navigation while it is muted.
+const MUTE_MEDIA_SCRIPT: &str = r#"
+document.querySelectorAll('audio, video').forEach(function(media) {
+    if (!media.muted) {
+        media.muted = true;
+        media.__webviewMutedByHost = true;
+    }
+});"#;
+
+/// Defines `window.__webviewFind` for [`WebView::find`] in the current document, unless it is
+/// already there. Matches are looked up in the visible text nodes and kept as ranges, all of
+/// them highlighted with the CSS Custom Highlight API where the runtime supports it, and the
+/// active one selected and scrolled into view.
+const FIND_SCRIPT: &str = r#"
+(function() {
+    if (window.__webviewFind) {
+        return;
+    }
+    var name = 'webview-find';
+    var canHighlight = window.CSS && CSS.highlights && window.Highlight;
+    var ranges = [];
+    var index = -1;
+    var report = function() {
+        var selection = window.getSelection();
+        selection.removeAllRanges();
+        if (index >= 0) {
+            var range = ranges[index];
+            selection.addRange(range);
+            range.startContainer.parentElement.scrollIntoView({ block: 'center' });
+        }
+        return { matchCount: ranges.length, activeMatchIndex: index >= 0 ? index : null };
+    };
+    window.__webviewFind = {
+        find: function(query, caseSensitive, wholeWord) {
+            this.stop();
+            if (query && document.body) {
+                var escaped = query.replace(/[.*+?^${}()|[\]\\]/g, '\\$&');
+                var pattern = new RegExp(
+                    wholeWord ? '\\b' + escaped + '\\b' : escaped,
+                    caseSensitive ? 'g' : 'gi');
+                var walker = document.createTreeWalker(document.body, NodeFilter.SHOW_TEXT, {
+                    acceptNode: function(node) {
+                        var parent = node.parentElement;
+                        return !parent
+                            || /^(SCRIPT|STYLE|NOSCRIPT|TEMPLATE)$/.test(parent.tagName)
+                            || parent.getClientRects().length === 0
+                            ? NodeFilter.FILTER_REJECT
+                            : NodeFilter.FILTER_ACCEPT;
+                    }
+                });
+                for (var node = walker.nextNode(); node; node = walker.nextNode()) {
+                    var match;
+                    while ((match = pattern.exec(node.data)) !== null) {
+                        var range = document.createRange();
+                        range.setStart(node, match.index);
+                        range.setEnd(node, match.index + match[0].length);
+                        ranges.push(range);
+                    }
+                }
+            }
+            if (ranges.length > 0) {
+                index = 0;
+                if (canHighlight) {
+                    if (!document.getElementById(name)) {
+                        var style = document.createElement('style');
+                        style.id = name;
+                        style.textContent =
+                            '::highlight(' + name + ') { background-color: #ff0; color: #000; }';
+                        document.head.appendChild(style);
+                    }
+                    CSS.highlights.set(name, new Highlight(...ranges));
+                }
+            }
+            return report();
+        },
+        step: function(delta) {
+            if (ranges.length > 0) {
+                index = (index + delta + ranges.length) % ranges.length;
+            }
+            return report();
+        },
+        stop: function() {
+            if (canHighlight) {
+                CSS.highlights.delete(name);
+            }
+            if (index >= 0) {
+                window.getSelection().removeAllRanges();
+            }
+            ranges = [];
+            index = -1;
+        }
+    };
+})();"#;
+
+/// Stops swipe navigation in each document while [`WebView::set_swipe_navigation_enabled`] is
+/// off. Init scripts may run before the root element exists, so wait for it if need be.
+const NO_SWIPE_SCRIPT: &str = r#"
+(function() {
+    var apply = function() {
+        document.documentElement.style.setProperty('overscroll-behavior-x', 'none', 'important');
+    };
+    if (document.documentElement) {
+        apply();
+    } else {
+        document.addEventListener('DOMContentLoaded', apply);
+    }
+})();"#;
+
+/// The virtual host name [`WebView::load_html`] maps to its temporary folder.
+const LOAD_HTML_HOST: &str = "load-html.example";
+
+/// How long to wait for WebView2 async operations unless [`WebViewBuilder::with_timeout`] says
+/// otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a dropped [`TempProfile`] keeps trying to delete the folder.
+const TEMP_PROFILE_CLEANUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The start of the names of [`TempProfile`] folders, followed by the process ID.
+const TEMP_PROFILE_PREFIX: &str = "webview2-profile-";
+
+/// Returned by [`msg_wait`] for the first handle, or for messages in the queue when it is not
+/// waiting on any handles.
+const WAIT_OBJECT_0: u32 = 0;
+
+/// Returned by [`msg_wait`] with a single handle when there are messages in the queue.
+const WAIT_INPUT: u32 = WAIT_OBJECT_0 + 1;
+
+/// Tells [`msg_wait`] to wait without a timeout.
+const INFINITE: u32 = u32::MAX;
+
+/// Returned by [`msg_wait`] when it fails.
+const WAIT_FAILED: u32 = u32::MAX;
+
+/// Cast to a newer version of a WebView2 interface, returning [`Error::UnsupportedInterface`]
+/// if the installed runtime does not implement it yet.
+fn cast_interface<T: Interface, I: Interface>(source: &T, name: &'static str) -> Result<I> {
+    source.cast().map_err(|_| Error::UnsupportedInterface(name))
+}
+
 extern "system" fn window_proc(hwnd: HWND, msg: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
     let webview = match WebView::get_window_webview(hwnd) {
         Some(webview) => webview,
@@ -610,21 +5823,115 @@ extern "system" fn window_proc(hwnd: HWND, msg: u32, w_param: WPARAM, l_param: L
                 webview
                     .controller
                     .0
-                    .put_Bounds(RECT {
-                        left: 0,
-                        top: 0,
-                        right: size.cx,
-                        bottom: size.cy,
-                    })
+                    .put_Bounds(client_bounds(&webview.controller.0, size))
                     .unwrap();
             }
-            *frame.size.lock().expect("lock size") = size;
+            // There's no way to report an error from the window procedure, so skip updating the
+            // cached size if another thread panicked while holding the lock.
+            if let Ok(mut frame_size) = frame.size.lock() {
+                *frame_size = size;
+            }
             LRESULT(0)
         }
 
-        WindowsAndMessaging::WM_CLOSE => {
+        WindowsAndMessaging::WM_ERASEBKGND => {
+            let background_color = frame.background_color.lock().ok().and_then(|color| *color);
+            if let Some(argb) = background_color {
+                // `COLORREF` is `0x00BBGGRR`.
+                let color = ((argb & 0xff) << 16) | (argb & 0xff00) | ((argb >> 16) & 0xff);
+                let mut client_rect = RECT::default();
+                unsafe {
+                    WindowsAndMessaging::GetClientRect(hwnd, &mut client_rect);
+                    let brush = Gdi::CreateSolidBrush(color);
+                    Gdi::FillRect(Gdi::HDC(w_param.0 as isize), &client_rect, brush);
+                    Gdi::DeleteObject(Gdi::HGDIOBJ(brush.0));
+                }
+            }
+            // Report the background as erased either way, so Windows does not fill it with the
+            // class brush.
+            LRESULT(1)
+        }
+
+        WindowsAndMessaging::WM_GETMINMAXINFO => {
+            let info = unsafe { &mut *(l_param.0 as *mut MINMAXINFO) };
+            if let Ok(Some(min_size)) = frame.min_size.lock().as_deref() {
+                info.ptMinTrackSize = POINT {
+                    x: min_size.cx,
+                    y: min_size.cy,
+                };
+            }
+            if let Ok(Some(max_size)) = frame.max_size.lock().as_deref() {
+                info.ptMaxTrackSize = POINT {
+                    x: max_size.cx,
+                    y: max_size.cy,
+                };
+                info.ptMaxSize = info.ptMaxTrackSize;
+            }
+            LRESULT(0)
+        }
+
+        WindowsAndMessaging::WM_DPICHANGED => {
+            // Resize to the rectangle Windows suggests for the new DPI, which triggers `WM_SIZE`
+            // and updates the controller bounds.
+            let suggested = unsafe { *(l_param.0 as *const RECT) };
             unsafe {
-                WindowsAndMessaging::DestroyWindow(hwnd);
+                WindowsAndMessaging::SetWindowPos(
+                    hwnd,
+                    None,
+                    suggested.left,
+                    suggested.top,
+                    suggested.right - suggested.left,
+                    suggested.bottom - suggested.top,
+                    WindowsAndMessaging::SWP_NOZORDER | WindowsAndMessaging::SWP_NOACTIVATE,
+                );
+            }
+
+            // Keep the rasterization scale in sync with the monitor unless it has been set
+            // manually with `set_rasterization_scale`.
+            if let Ok(controller) = webview.controller.0.cast::<ICoreWebView2Controller3>() {
+                let mut detect = BOOL::default();
+                unsafe {
+                    if controller
+                        .get_ShouldDetectMonitorScaleChanges(&mut detect)
+                        .is_ok()
+                        && detect.as_bool()
+                    {
+                        let dpi = (w_param.0 & 0xffff) as f64;
+                        let _ = controller.put_RasterizationScale(dpi / 96.0);
+                    }
+                }
+            }
+
+            // `WM_SIZE` used the old scale, which matters if the bounds are in DIPs.
+            let _ = webview.update_frame_bounds();
+            LRESULT(0)
+        }
+
+        WindowsAndMessaging::WM_CLOSE => {
+            // Release the lock while `f` runs, so it can show a dialog or replace itself with
+            // `on_close_requested` without deadlocking.
+            let f = frame
+                .close_requested
+                .lock()
+                .ok()
+                .and_then(|mut close_requested| close_requested.take());
+            let close = match f {
+                Some(mut f) => {
+                    let close = f();
+                    if let Ok(mut close_requested) = frame.close_requested.lock() {
+                        if close_requested.is_none() {
+                            *close_requested = Some(f);
+                        }
+                    }
+                    close
+                }
+                None => true,
+            };
+
+            if close {
+                unsafe {
+                    WindowsAndMessaging::DestroyWindow(hwnd);
+                }
             }
             LRESULT(0)
         }
@@ -638,6 +5945,32 @@ extern "system" fn window_proc(hwnd: HWND, msg: u32, w_param: WPARAM, l_param: L
     }
 }
 
+/// Get the bounds which cover a client area of `size` physical pixels, divided by the
+/// rasterization scale if `controller` is in [`BoundsMode::RasterizationScale`].
+fn client_bounds(controller: &ICoreWebView2Controller, size: SIZE) -> RECT {
+    let mut bounds = RECT {
+        left: 0,
+        top: 0,
+        right: size.cx,
+        bottom: size.cy,
+    };
+    if let Ok(controller) = controller.cast::<ICoreWebView2Controller3>() {
+        let mut mode = COREWEBVIEW2_BOUNDS_MODE::default();
+        let mut scale = 1.0;
+        unsafe {
+            if controller.get_BoundsMode(&mut mode).is_ok()
+                && mode == COREWEBVIEW2_BOUNDS_MODE_USE_RASTERIZATION_SCALE
+                && controller.get_RasterizationScale(&mut scale).is_ok()
+                && scale > 0.0
+            {
+                bounds.right = (f64::from(size.cx) / scale).round() as i32;
+                bounds.bottom = (f64::from(size.cy) / scale).round() as i32;
+            }
+        }
+    }
+    bounds
+}
+
 fn get_window_size(hwnd: HWND) -> SIZE {
     let mut client_rect = RECT::default();
     unsafe { WindowsAndMessaging::GetClientRect(hwnd, &mut client_rect) };
@@ -652,28 +5985,154 @@ fn get_window_size(hwnd: HWND) -> SIZE {
 /// to finish before starting the main message loop in `WebView::run`. As long as there are no pending
 /// results in `rx`, it will pump Window messages and check for a result after each message is dispatched.
 ///
-/// `GetMessage` is a blocking call, so if we want to send results from another thread, senders from other
+/// [`msg_wait`] blocks until there is a message in the queue or `timeout` has elapsed, in which case
+/// this returns [`Error::Timeout`]. If we want to send results from another thread, senders from other
 /// threads should "kick" the message loop after sending the result by calling `PostThreadMessage` with an
 /// ignorable/unhandled message such as `WM_APP`.
-fn wait_with_pump<T>(rx: mpsc::Receiver<T>) -> Result<T> {
+fn wait_with_pump<T>(rx: mpsc::Receiver<T>, timeout: Duration) -> Result<T> {
     let mut msg = MSG::default();
     let hwnd = HWND::default();
+    let deadline = Instant::now() + timeout;
 
     loop {
         if let Ok(result) = rx.try_recv() {
             return Ok(result);
         }
 
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let milliseconds = u32::try_from(remaining.as_millis()).unwrap_or(u32::MAX - 1);
+
         unsafe {
-            match WindowsAndMessaging::GetMessageA(&mut msg, hwnd, 0, 0).0 {
-                -1 => {
-                    return Err(windows::Error::from_win32().into());
+            match msg_wait(&[], milliseconds) {
+                WAIT_OBJECT_0 => {
+                    while WindowsAndMessaging::PeekMessageA(
+                        &mut msg,
+                        hwnd,
+                        0,
+                        0,
+                        WindowsAndMessaging::PM_REMOVE,
+                    )
+                    .as_bool()
+                    {
+                        if msg.message == WindowsAndMessaging::WM_QUIT {
+                            return Err(Error::TaskCanceled);
+                        }
+
+                        WindowsAndMessaging::TranslateMessage(&msg);
+                        WindowsAndMessaging::DispatchMessageA(&msg);
+
+                        if let Ok(result) = rx.try_recv() {
+                            return Ok(result);
+                        }
+                    }
                 }
-                0 => return Err(Error::TaskCanceled),
+                WAIT_FAILED => return Err(windows::Error::from_win32().into()),
                 _ => {
-                    WindowsAndMessaging::TranslateMessage(&msg);
-                    WindowsAndMessaging::DispatchMessageA(&msg);
+                    logger::log(format_args!(
+                        "async operation timed out after {:?}",
+                        timeout
+                    ));
+                    return Err(Error::Timeout);
+                }
+            }
+        }
+    }
+}
+
+/// Block until either a closure is dispatched, which signals `dispatch_event`, or there are
+/// messages in the queue, and dispatch all of them, for [`WebView::run`]. Returns `false` once
+/// `WM_QUIT` is received, or [`Error::Timeout`] if nothing happened within `milliseconds`.
+///
+/// Senders on other threads only need to signal the event, and messages which a nested pump,
+/// e.g. [`wait_with_pump`] in a dispatched closure, left in the queue wake this up as well.
+fn wait_for_dispatch(dispatch_event: &DispatchEvent, milliseconds: u32) -> Result<bool> {
+    let mut msg = MSG::default();
+    let hwnd = HWND::default();
+
+    match msg_wait(&[dispatch_event.0], milliseconds) {
+        WAIT_OBJECT_0 => Ok(true),
+        WAIT_INPUT => {
+            unsafe {
+                while WindowsAndMessaging::PeekMessageA(
+                    &mut msg,
+                    hwnd,
+                    0,
+                    0,
+                    WindowsAndMessaging::PM_REMOVE,
+                )
+                .as_bool()
+                {
+                    match msg.message {
+                        WindowsAndMessaging::WM_QUIT => return Ok(false),
+                        WindowsAndMessaging::WM_APP => (),
+                        _ => {
+                            WindowsAndMessaging::TranslateMessage(&msg);
+                            WindowsAndMessaging::DispatchMessageA(&msg);
+                        }
+                    }
+                }
+            }
+            Ok(true)
+        }
+        WAIT_FAILED => Err(windows::Error::from_win32().into()),
+        _ => Err(Error::Timeout),
+    }
+}
+
+/// Wait until one of `handles` is signaled, there are messages in the queue, or `milliseconds`
+/// have elapsed, and return which like `MsgWaitForMultipleObjects`.
+///
+/// Without `MWMO_INPUTAVAILABLE`, `MsgWaitForMultipleObjects` only wakes up for messages which
+/// arrived since the last time the queue was checked, so messages left behind by a loop which
+/// returned early, e.g. [`wait_with_pump`] once it has its result, would wait for the next one.
+fn msg_wait(handles: &[HANDLE], milliseconds: u32) -> u32 {
+    unsafe {
+        WindowsAndMessaging::MsgWaitForMultipleObjectsEx(
+            handles.len() as u32,
+            handles.as_ptr(),
+            milliseconds,
+            WindowsAndMessaging::QS_ALLINPUT,
+            WindowsAndMessaging::MWMO_INPUTAVAILABLE,
+        )
+    }
+}
+
+/// Pump Window messages like [`wait_with_pump`] until `duration` has elapsed, e.g. to let the
+/// page make progress between polls. Returns [`Error::TaskCanceled`] if `WM_QUIT` is received.
+fn pump_for(duration: Duration) -> Result<()> {
+    let mut msg = MSG::default();
+    let hwnd = HWND::default();
+    let deadline = Instant::now() + duration;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_millis(0) {
+            return Ok(());
+        }
+        let milliseconds = u32::try_from(remaining.as_millis()).unwrap_or(u32::MAX - 1);
+
+        unsafe {
+            match msg_wait(&[], milliseconds) {
+                WAIT_OBJECT_0 => {
+                    while WindowsAndMessaging::PeekMessageA(
+                        &mut msg,
+                        hwnd,
+                        0,
+                        0,
+                        WindowsAndMessaging::PM_REMOVE,
+                    )
+                    .as_bool()
+                    {
+                        if msg.message == WindowsAndMessaging::WM_QUIT {
+                            return Err(Error::TaskCanceled);
+                        }
+
+                        WindowsAndMessaging::TranslateMessage(&msg);
+                        WindowsAndMessaging::DispatchMessageA(&msg);
+                    }
                 }
+                WAIT_FAILED => return Err(windows::Error::from_win32().into()),
+                _ => return Ok(()),
             }
         }
     }
@@ -702,3 +6161,217 @@ unsafe fn GetWindowLong(window: HWND, index: WINDOW_LONG_PTR_INDEX) -> isize {
 unsafe fn GetWindowLong(window: HWND, index: WINDOW_LONG_PTR_INDEX) -> isize {
     WindowsAndMessaging::GetWindowLongPtrA(window, index)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worker_thread_wakes_dispatch_wait_without_wm_app() {
+        let dispatch_event = Arc::new(DispatchEvent::new().unwrap());
+        let (tx, rx) = mpsc::channel();
+        let worker = {
+            let dispatch_event = dispatch_event.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                tx.send(42).unwrap();
+                dispatch_event.signal();
+            })
+        };
+
+        assert!(wait_for_dispatch(&dispatch_event, 5000).unwrap());
+        assert_eq!(rx.try_recv().unwrap(), 42);
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn available_browser_version_is_dotted_numbers() {
+        match available_browser_version() {
+            Ok(version) => {
+                // Preview channels append their name, e.g. `"96.0.1054.8 beta"`.
+                let version = version.split_whitespace().next().unwrap();
+                let parts = version.split('.').collect::<Vec<_>>();
+                assert_eq!(parts.len(), 4, "unexpected version {:?}", version);
+                assert!(parts.iter().all(|part| part.parse::<u32>().is_ok()));
+                assert_eq!(
+                    compare_browser_versions(version, version).unwrap(),
+                    std::cmp::Ordering::Equal
+                );
+            }
+            Err(Error::RuntimeNotInstalled) => {}
+            Err(err) => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn compare_browser_versions_compares_each_part_as_a_number() {
+        assert_eq!(
+            compare_browser_versions("94.0.992.31", "100.0.1185.36").unwrap(),
+            std::cmp::Ordering::Less
+        );
+        assert_eq!(
+            compare_browser_versions("94.0.992.31", "94.0.992.9").unwrap(),
+            std::cmp::Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn default_cert_action_shows_the_error_page_for_navigations() {
+        assert_eq!(
+            CertAction::Default.devtools_response(true),
+            ("cancel", true)
+        );
+        // Subresources have no error page, so the default is to fail them.
+        assert_eq!(
+            CertAction::Default.devtools_response(false),
+            ("cancel", false)
+        );
+        assert_eq!(
+            CertAction::Cancel.devtools_response(true),
+            ("cancel", false)
+        );
+        assert_eq!(
+            CertAction::AlwaysAllow.devtools_response(true),
+            ("continue", false)
+        );
+    }
+
+    /// Initialize COM on the test thread and create a `WebView` with its own frame window.
+    fn create_test_webview() -> WebView {
+        unsafe { CoInitializeEx(ptr::null_mut(), COINIT_APARTMENTTHREADED) }.unwrap();
+        WebView::create(None, false).unwrap()
+    }
+
+    /// Panic on this thread while holding `lock`, which poisons it.
+    fn poison<T>(lock: &Mutex<T>) {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _guard = lock.lock().unwrap();
+            panic!("poison the lock");
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[ignore = "needs the WebView2 runtime"]
+    fn poisoned_lock_returns_lock_error() {
+        let webview = create_test_webview();
+
+        poison(&webview.url);
+        assert!(matches!(
+            webview.navigate("https://example.com"),
+            Err(Error::LockError)
+        ));
+
+        poison(&webview.frame.as_ref().unwrap().size);
+        assert!(matches!(webview.set_size(640, 480), Err(Error::LockError)));
+    }
+
+    #[test]
+    #[ignore = "needs the WebView2 runtime"]
+    fn dropping_every_clone_quits_the_message_loop() {
+        thread_local! {
+            static CLONES: std::cell::RefCell<Vec<WebView>> = Default::default();
+        }
+
+        let webview = create_test_webview();
+        CLONES.with(|clones| {
+            clones
+                .borrow_mut()
+                .extend(vec![webview.clone(), webview.clone()])
+        });
+        webview
+            .dispatch(|_webview| CLONES.with(|clones| clones.borrow_mut().clear()))
+            .unwrap();
+
+        // Like `terminate_after`, but remember whether it had to.
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let handle = webview.handle();
+        std::thread::spawn({
+            let timed_out = timed_out.clone();
+            move || {
+                std::thread::sleep(Duration::from_secs(10));
+                timed_out.store(true, Ordering::SeqCst);
+                let _ = handle.dispatch(|webview| {
+                    let _ = webview.terminate();
+                });
+            }
+        });
+
+        assert!(webview.run().is_ok());
+        assert!(!timed_out.load(Ordering::SeqCst));
+    }
+
+    /// Terminate the `WebView` from a worker thread after `timeout`, so a test waiting for an
+    /// event which never fires fails instead of hanging.
+    fn terminate_after(webview: &WebView, timeout: Duration) {
+        let handle = webview.handle();
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            let _ = handle.dispatch(|webview| {
+                let _ = webview.terminate();
+            });
+        });
+    }
+
+    #[test]
+    #[ignore = "needs the WebView2 runtime"]
+    fn run_focuses_the_contents() {
+        let webview = create_test_webview();
+        let got_focus = Arc::new(AtomicBool::new(false));
+        let handle = webview.handle();
+        webview
+            .on_got_focus({
+                let got_focus = got_focus.clone();
+                move || {
+                    got_focus.store(true, Ordering::SeqCst);
+                    let _ = handle.dispatch(|webview| {
+                        let _ = webview.terminate();
+                    });
+                }
+            })
+            .unwrap()
+            .detach();
+        terminate_after(&webview, Duration::from_secs(10));
+
+        webview.run().unwrap();
+        assert!(got_focus.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[ignore = "needs the WebView2 runtime"]
+    fn panicking_binding_rejects_the_promise() {
+        let webview = create_test_webview();
+        let outcome = Arc::new(Mutex::new(None));
+        webview
+            .bind("panics", |_params| panic!("binding panicked on purpose"))
+            .unwrap();
+        let handle = webview.handle();
+        webview
+            .bind("report", {
+                let outcome = outcome.clone();
+                move |params| {
+                    *outcome.lock()? = params.into_iter().next();
+                    handle.dispatch(|webview| {
+                        let _ = webview.terminate();
+                    })?;
+                    Ok(Value::Null)
+                }
+            })
+            .unwrap()
+            .load_html(
+                r#"<script>
+                window.panics().then(
+                    function() { window.report('resolved'); },
+                    function() { window.report('rejected'); });
+                </script>"#,
+            )
+            .unwrap();
+        terminate_after(&webview, Duration::from_secs(10));
+
+        webview.run().unwrap();
+        assert_eq!(
+            *outcome.lock().unwrap(),
+            Some(Value::String(String::from("rejected")))
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use windows::implement;
+
+use bindings::{
+    Microsoft::{self, Web::WebView2::Win32::*},
+    Windows::{
+        self,
+        Win32::Foundation::{BOOL, E_POINTER, PWSTR},
+    },
+};
+
+use super::pwstr::{pwstr_from_str, string_from_pwstr};
+
+/// Implementation of [`ICoreWebView2EnvironmentOptions`], which the WebView2 SDK only provides
+/// as a C++ class, for passing to `CreateCoreWebView2EnvironmentWithOptions`.
+#[implement(Microsoft::Web::WebView2::Win32::ICoreWebView2EnvironmentOptions)]
+#[derive(Default)]
+pub struct EnvironmentOptions {
+    additional_browser_arguments: String,
+    language: String,
+    target_compatible_browser_version: String,
+    allow_single_sign_on_using_os_primary_account: bool,
+}
+
+#[allow(non_snake_case)]
+impl EnvironmentOptions {
+    pub fn create(language: &str) -> ICoreWebView2EnvironmentOptions {
+        Self {
+            language: String::from(language),
+            ..Self::default()
+        }
+        .into()
+    }
+
+    fn get_AdditionalBrowserArguments(&mut self, value: *mut PWSTR) -> ::windows::Result<()> {
+        set_out_param(value, &self.additional_browser_arguments)
+    }
+
+    fn put_AdditionalBrowserArguments(&mut self, value: PWSTR) -> ::windows::Result<()> {
+        self.additional_browser_arguments = string_from_pwstr(value);
+        Ok(())
+    }
+
+    fn get_Language(&mut self, value: *mut PWSTR) -> ::windows::Result<()> {
+        set_out_param(value, &self.language)
+    }
+
+    fn put_Language(&mut self, value: PWSTR) -> ::windows::Result<()> {
+        self.language = string_from_pwstr(value);
+        Ok(())
+    }
+
+    fn get_TargetCompatibleBrowserVersion(&mut self, value: *mut PWSTR) -> ::windows::Result<()> {
+        set_out_param(value, &self.target_compatible_browser_version)
+    }
+
+    fn put_TargetCompatibleBrowserVersion(&mut self, value: PWSTR) -> ::windows::Result<()> {
+        self.target_compatible_browser_version = string_from_pwstr(value);
+        Ok(())
+    }
+
+    fn get_AllowSingleSignOnUsingOSPrimaryAccount(
+        &mut self,
+        allow: *mut BOOL,
+    ) -> ::windows::Result<()> {
+        if allow.is_null() {
+            return Err(::windows::Error::fast_error(E_POINTER));
+        }
+        unsafe { *allow = self.allow_single_sign_on_using_os_primary_account.into() };
+        Ok(())
+    }
+
+    fn put_AllowSingleSignOnUsingOSPrimaryAccount(&mut self, allow: BOOL) -> ::windows::Result<()> {
+        self.allow_single_sign_on_using_os_primary_account = allow.as_bool();
+        Ok(())
+    }
+}
+
+/// Copy `source` to a `PWSTR` output param, or leave it null if `source` is empty so WebView2
+/// uses its default.
+fn set_out_param(value: *mut PWSTR, source: &str) -> ::windows::Result<()> {
+    if value.is_null() {
+        return Err(::windows::Error::fast_error(E_POINTER));
+    }
+    unsafe {
+        *value = if source.is_empty() {
+            PWSTR::default()
+        } else {
+            pwstr_from_str(source)
+        };
+    }
+    Ok(())
+}
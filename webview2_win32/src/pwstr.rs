@@ -34,3 +34,18 @@ pub fn take_pwstr(source: PWSTR) -> String {
 
     result
 }
+
+/// Copy a [`str`] to a new [`PWSTR`] allocated with [`Com::CoTaskMemAlloc`], e.g. for an output
+/// param which the caller frees with [`Com::CoTaskMemFree`].
+pub fn pwstr_from_str(source: &str) -> PWSTR {
+    let buffer: Vec<u16> = source.encode_utf16().chain(Some(0)).collect();
+    let size = buffer.len() * mem::size_of::<u16>();
+
+    unsafe {
+        let pwz = Com::CoTaskMemAlloc(size) as *mut u16;
+        if !pwz.is_null() {
+            buffer.as_ptr().copy_to_nonoverlapping(pwz, buffer.len());
+        }
+        PWSTR(pwz)
+    }
+}